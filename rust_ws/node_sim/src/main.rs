@@ -14,6 +14,9 @@ mod cli;
 async fn main() {
     let cli = cli::Args::parse();
     let mut clients = HashMap::new();
+    // Consumers drop detections with repeated sequence numbers
+    let mut seqs: HashMap<u16, u32> = HashMap::new();
+    let boot_id = std::process::id();
 
     // Connect a client for each node
     for node_id in cli.node_ids {
@@ -76,14 +79,19 @@ async fn main() {
                 if let Ok(nodeid) = node.trim().parse::<u16>() {
                     let dist = input("Distance(mm)?\n");
                     if let Ok(dist) = dist.trim().parse::<u32>() {
+                        let seq = seqs.entry(nodeid).or_default();
                         let fut = clients.get(&nodeid).map(|client| {
                             let now = SystemTime::now();
-                            client.publish(Detection(DetectionMessage::new(
-                                nodeid,
-                                dist,
-                                now.duration_since(UNIX_EPOCH).unwrap().as_secs(),
-                                now.duration_since(UNIX_EPOCH).unwrap().subsec_nanos(),
-                            )))
+                            *seq += 1;
+                            client.publish(Detection(
+                                DetectionMessage::new(
+                                    nodeid,
+                                    dist,
+                                    now.duration_since(UNIX_EPOCH).unwrap().as_secs(),
+                                    now.duration_since(UNIX_EPOCH).unwrap().subsec_nanos(),
+                                )
+                                .with_seq(boot_id, *seq),
+                            ))
                         });
 
                         if let Some(fut) = fut {
@@ -1,7 +1,255 @@
 //! Sensor node application logic
 
 use crate::dist_sensor::{DistanceReading, DistanceSensor, SensorError};
-use std::time::Instant;
+use crate::error::ZeroError;
+use std::time::{Duration, Instant, SystemTime};
+use tf_luna::types::Validity;
+
+/// Most readings used in a zero, so fast sensors don't use unbounded memory
+const MAX_ZERO_SAMPLES: usize = 1000;
+/// Failed reads in a row before zeroing gives up, so a dead sensor doesn't spin for the window
+const MAX_ZERO_READ_ERRORS: u32 = 5;
+/// Fraction of readings dropped from each end when zeroing, so passing people don't skew it
+const ZERO_TRIM: f64 = 0.1;
+/// Clear readings in a row for a vehicle to have left, so gaps like windows don't split it
+const EXIT_SAMPLES: u32 = 3;
+/// Furthest apart readings can be to interpolate between them
+const MAX_INTERPOLATION_GAP: Duration = Duration::from_millis(50);
+/// Time after a vehicle that the baseline ignores readings
+const BASELINE_HOLD_OFF: Duration = Duration::from_secs(1);
+/// Longest a vehicle can be in view, so a parked car doesn't hold up detections forever
+const MAX_DWELL: Duration = Duration::from_secs(10);
+
+/// How the sensor is zeroed
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ZeroSettings {
+    /// Time to collect readings over
+    pub window: Duration,
+    /// Largest standard deviation of readings to accept, mm
+    pub max_stddev: u32,
+}
+
+impl Default for ZeroSettings {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(1),
+            max_stddev: 50,
+        }
+    }
+}
+
+/// When the sensor re-arms after a vehicle. All modes wait for the vehicle to leave first, so a
+/// parked car is only seen once.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Debounce {
+    /// Once nothing has been in view for this long, so slow cars aren't seen twice
+    Clear(Duration),
+    /// This long after the last vehicle arrived
+    MinGap(Duration),
+    /// As soon as the last vehicle leaves, so each car of a closely following train is seen
+    Train,
+}
+
+impl Debounce {
+    /// Hold off time of the mode, zero if it has none.
+    pub fn duration(&self) -> Duration {
+        match self {
+            Debounce::Clear(duration) | Debounce::MinGap(duration) => *duration,
+            Debounce::Train => Duration::ZERO,
+        }
+    }
+
+    /// The same mode with another hold off time.
+    pub fn with_duration(self, duration: Duration) -> Self {
+        match self {
+            Debounce::Clear(_) => Debounce::Clear(duration),
+            Debounce::MinGap(_) => Debounce::MinGap(duration),
+            Debounce::Train => Debounce::Train,
+        }
+    }
+}
+
+/// How the zero follows slow changes in the background, like a target heating in the sun
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct BaselineSettings {
+    /// Time constant of the moving average. Zero disables tracking.
+    pub time_constant: Duration,
+    /// Furthest the zero can move from where it was last zeroed, mm
+    pub max_drift: u32,
+}
+
+impl Default for BaselineSettings {
+    fn default() -> Self {
+        Self {
+            time_constant: Duration::from_secs(60),
+            max_drift: 100,
+        }
+    }
+}
+
+/// Outcome of zeroing the sensor
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ZeroStats {
+    /// New zero, mm
+    pub zero: u32,
+    /// Variance of the readings used, mm^2
+    pub variance: u32,
+    /// Readings used
+    pub samples: u32,
+}
+
+/// A vehicle passing the sensor, from when it entered view to when it left
+#[derive(Debug)]
+pub struct Detection {
+    /// First reading of the vehicle
+    pub entry: DistanceReading,
+    /// Monotonic time the vehicle crossed the threshold, between readings
+    pub instant: Instant,
+    /// Wall clock time the vehicle crossed the threshold, between readings
+    pub stamp: SystemTime,
+    /// Monotonic time the vehicle left view
+    pub exit_instant: Instant,
+    /// Wall clock time the vehicle left view
+    pub exit_stamp: SystemTime,
+    /// Closest the vehicle came, mm
+    pub min_dist: u32,
+    /// Readings the vehicle was seen in
+    pub samples: u32,
+    /// Distance a reading must be closer than to see the vehicle, mm
+    level: u32,
+    /// Last reading the vehicle was seen in
+    last_seen: DistanceReading,
+    /// Clear readings in a row since the vehicle was last seen
+    clear_samples: u32,
+}
+
+impl Detection {
+    /// Starts a pass. If the reading before entry was clear, it's used to find when the vehicle
+    /// crossed level.
+    fn new(entry: DistanceReading, before: Option<&DistanceReading>, level: u32) -> Self {
+        let (instant, stamp) = match before {
+            Some(before) => crossing(before, &entry, level),
+            None => (entry.instant, entry.stamp),
+        };
+
+        Self {
+            instant,
+            stamp,
+            exit_instant: entry.instant,
+            exit_stamp: entry.stamp,
+            min_dist: entry.dist,
+            samples: 1,
+            level,
+            last_seen: entry,
+            clear_samples: 0,
+            entry,
+        }
+    }
+
+    /// Adds a valid reading to the pass, returning true once the vehicle has left.
+    fn update(&mut self, reading: &DistanceReading, occluded: bool) -> bool {
+        if occluded {
+            self.exit_instant = reading.instant;
+            self.exit_stamp = reading.stamp;
+            self.min_dist = self.min_dist.min(reading.dist);
+            self.samples += 1;
+            self.last_seen = *reading;
+            self.clear_samples = 0;
+
+            if self.dwell() > MAX_DWELL {
+                log::warn!("Sensor blocked for over {:?}, ending detection", MAX_DWELL);
+                return true;
+            }
+            return false;
+        }
+
+        // The vehicle left between the last reading it was in and the first of the clear readings
+        if self.clear_samples == 0 {
+            (self.exit_instant, self.exit_stamp) = crossing(&self.last_seen, reading, self.level);
+        }
+        self.clear_samples += 1;
+
+        self.clear_samples >= EXIT_SAMPLES
+    }
+
+    /// Time the vehicle was in view.
+    pub fn dwell(&self) -> Duration {
+        self.exit_instant.duration_since(self.instant)
+    }
+}
+
+/// Linearly interpolates when distance crossed level between two consecutive readings.
+///
+/// Falls back to the later reading if they are too far apart to trust.
+fn crossing(from: &DistanceReading, to: &DistanceReading, level: u32) -> (Instant, SystemTime) {
+    let gap = to.instant.duration_since(from.instant);
+    if gap > MAX_INTERPOLATION_GAP {
+        return (to.instant, to.stamp);
+    }
+
+    let span = from.dist.abs_diff(to.dist);
+    let fraction = if span == 0 {
+        1.0
+    } else {
+        (from.dist.abs_diff(level) as f64 / span as f64).clamp(0.0, 1.0)
+    };
+
+    let offset = gap.mul_f64(fraction);
+    (from.instant + offset, from.stamp + offset)
+}
+
+/// Health of the sensor since it was last checked
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct SensorHealth {
+    /// Chip temperature of the latest reading with one, °C
+    pub temp_c: Option<f32>,
+    /// Mean signal strength, if the sensor reports it
+    pub mean_amp: Option<u16>,
+    /// Readings taken per second
+    pub readings_per_s: f64,
+    /// Frames that failed their checksum, since startup
+    pub checksum_failures: u64,
+    /// Failed reads, since startup
+    pub read_errors: u64,
+}
+
+/// Running counts behind [`SensorHealth`]
+struct HealthCounters {
+    /// When counting restarted
+    since: Instant,
+    readings: u32,
+    amp_sum: u64,
+    amp_readings: u32,
+    temp_c: Option<f32>,
+    read_errors: u64,
+}
+
+impl HealthCounters {
+    fn new() -> Self {
+        Self {
+            since: Instant::now(),
+            readings: 0,
+            amp_sum: 0,
+            amp_readings: 0,
+            temp_c: None,
+            read_errors: 0,
+        }
+    }
+
+    fn record(&mut self, reading: &Result<DistanceReading, SensorError>) {
+        let Ok(reading) = reading else {
+            self.read_errors += 1;
+            return;
+        };
+
+        self.readings += 1;
+        if let Some(amp) = reading.amp {
+            self.amp_sum += amp as u64;
+            self.amp_readings += 1;
+        }
+        self.temp_c = reading.temp_c.or(self.temp_c);
+    }
+}
 
 /// Sensor node specific application state
 pub struct ApplicationContext<T>
@@ -13,9 +261,31 @@ where
     zero: u32,
     /// Delta off of zero to count as a trigger, mm
     threshold: u32,
-    /// Last time we detected a car (Monotonic). Used to prevent the sensor from triggering over
-    /// and over again if the vehicle is slowly passing.
-    last_detection: Instant,
+    /// When to re-arm after a vehicle
+    debounce: Debounce,
+    /// How to zero
+    zero_settings: ZeroSettings,
+    /// How to track the background
+    baseline_settings: BaselineSettings,
+    /// Moving average of the background, mm. Zero is this rounded.
+    baseline: f64,
+    /// Zero from the last zeroing, that the baseline is limited to drifting from, mm
+    anchor: u32,
+    /// Instant of the last reading the baseline saw
+    last_baseline_reading: Option<Instant>,
+    /// Frame rate the sensor was set to, Hz. None if left at the sensor's own.
+    frame_rate: Option<u16>,
+    /// Vehicle currently passing, if any
+    event: Option<Detection>,
+    /// Last valid reading, if it was clear. Used to find when a vehicle arrived between readings.
+    last_clear: Option<DistanceReading>,
+    /// Last time a vehicle was in view (Monotonic)
+    last_blocked: Option<Instant>,
+    /// Last time a vehicle arrived (Monotonic)
+    last_entry: Option<Instant>,
+    /// If the last vehicle has left
+    seen_clear: bool,
+    health: HealthCounters,
 }
 
 impl<T: DistanceSensor> ApplicationContext<T> {
@@ -23,54 +293,328 @@ impl<T: DistanceSensor> ApplicationContext<T> {
     ///
     /// # Args
     /// `threshold` - The difference off zero to consider a trigger in mm
-    pub fn new(sensor: T, default_zero: u32, threshold: u32) -> Self {
+    /// `debounce` - When to re-arm after a vehicle
+    /// `zero_settings` - How to zero
+    /// `baseline_settings` - How to track the background between zeroing
+    pub fn new(
+        sensor: T,
+        default_zero: u32,
+        threshold: u32,
+        debounce: Debounce,
+        zero_settings: ZeroSettings,
+        baseline_settings: BaselineSettings,
+    ) -> Self {
         Self {
             sensor,
             zero: default_zero,
             threshold,
-            last_detection: Instant::now(),
+            debounce,
+            zero_settings,
+            baseline_settings,
+            baseline: default_zero as f64,
+            anchor: default_zero,
+            last_baseline_reading: None,
+            frame_rate: None,
+            event: None,
+            last_clear: None,
+            last_blocked: None,
+            last_entry: None,
+            seen_clear: true,
+            health: HealthCounters::new(),
         }
     }
 
-    /// Zeros the sensor. New zero is stored internally, and also returned.
-    pub async fn zero(&mut self) -> Result<u32, SensorError> {
-        let mut avg = 0;
+    /// Delta off of zero to count as a trigger, mm.
+    pub fn threshold(&self) -> u32 {
+        self.threshold
+    }
+
+    pub fn set_threshold(&mut self, threshold: u32) {
+        self.threshold = threshold;
+    }
+
+    /// Hold off time after a vehicle, zero if the debounce mode has none.
+    pub fn debounce(&self) -> Duration {
+        self.debounce.duration()
+    }
+
+    /// Sets the hold off time after a vehicle, keeping the debounce mode. Returns false if the
+    /// mode has no hold off time, so nothing was set.
+    pub fn set_debounce(&mut self, debounce: Duration) -> bool {
+        self.debounce = self.debounce.with_duration(debounce);
+        self.debounce != Debounce::Train
+    }
+
+    /// Frame rate the sensor was set to, Hz.
+    pub fn frame_rate(&self) -> Option<u16> {
+        self.frame_rate
+    }
+
+    /// Sets the sensor frame rate. The old rate is kept if the sensor rejects it.
+    pub async fn set_frame_rate(&mut self, rate: u16) -> Result<(), SensorError> {
+        self.sensor.set_frame_rate(rate).await?;
+        self.frame_rate = Some(rate);
+        Ok(())
+    }
+
+    /// Current sensor zero, mm.
+    pub fn current_zero(&self) -> u32 {
+        self.zero
+    }
+
+    /// Health of the sensor since this was last called, or startup.
+    pub fn take_health(&mut self) -> SensorHealth {
+        let counters = &mut self.health;
+        let elapsed = counters.since.elapsed().as_secs_f64();
+
+        let health = SensorHealth {
+            temp_c: counters.temp_c,
+            mean_amp: (counters.amp_readings > 0)
+                .then(|| (counters.amp_sum / counters.amp_readings as u64) as u16),
+            readings_per_s: if elapsed > 0.0 {
+                counters.readings as f64 / elapsed
+            } else {
+                0.0
+            },
+            checksum_failures: self.sensor.checksum_failures(),
+            read_errors: counters.read_errors,
+        };
+
+        counters.since = Instant::now();
+        counters.readings = 0;
+        counters.amp_sum = 0;
+        counters.amp_readings = 0;
+        health
+    }
+
+    /// Reads the sensor, counting towards its health.
+    async fn read(&mut self) -> Result<DistanceReading, SensorError> {
+        let reading = self.sensor.get_reading().await;
+        self.health.record(&reading);
+        reading
+    }
 
-        for _ in 1..=100 {
-            avg += self.sensor.get_reading().await?.dist;
+    /// Zeros the sensor off of the trimmed mean of valid readings over the zero window.
+    ///
+    /// Failed reads are skipped, until too many fail in a row. If too few readings are valid, or
+    /// they are unstable, the previous zero is kept and the reason returned.
+    pub async fn zero(&mut self) -> Result<ZeroStats, ZeroError> {
+        let deadline = tokio::time::Instant::now() + self.zero_settings.window;
+        let mut dists = Vec::new();
+        let mut out_of_range = 0;
+        let mut bad_signal = 0;
+        let mut read_errors = 0;
+
+        while dists.len() + out_of_range + bad_signal < MAX_ZERO_SAMPLES {
+            let reading = match tokio::time::timeout_at(deadline, self.read()).await {
+                Ok(Ok(reading)) => reading,
+                Ok(Err(err)) => {
+                    log::warn!("Skipping failed read while zeroing: {}", err);
+                    read_errors += 1;
+                    if read_errors >= MAX_ZERO_READ_ERRORS {
+                        log::error!(
+                            "Sensor failed {} reads in a row, zeroing early",
+                            read_errors
+                        );
+                        break;
+                    }
+                    continue;
+                }
+                Err(_) => break,
+            };
+            read_errors = 0;
+
+            match reading.validity {
+                Validity::Valid => dists.push(reading.dist),
+                Validity::LowAmp | Validity::Saturated => bad_signal += 1,
+                Validity::OutOfRange | Validity::Sentinel => out_of_range += 1,
+            }
         }
 
-        let zero = avg / 100;
-        self.zero = zero;
+        let total = dists.len() + out_of_range + bad_signal;
+        if total == 0 {
+            return Err(ZeroError::NoReadings);
+        }
+
+        // Most readings must be good, else the zero is probably off of a few lucky ones
+        if dists.len() * 2 < total {
+            let percent = (dists.len() * 100 / total) as u32;
+            return Err(if out_of_range >= bad_signal {
+                ZeroError::NoWall(percent)
+            } else {
+                ZeroError::LowSignal(percent)
+            });
+        }
+
+        let (zero, variance) = trimmed_stats(&mut dists);
+        if variance as u64 > (self.zero_settings.max_stddev as u64).pow(2) {
+            return Err(ZeroError::Unstable(variance));
+        }
 
-        log::debug!("Set zero to {}mm", zero);
+        self.zero = zero;
+        self.baseline = zero as f64;
+        self.anchor = zero;
+        log::debug!("Set zero to {}mm, with variance {}mm^2", zero, variance);
 
-        Ok(zero)
+        Ok(ZeroStats {
+            zero,
+            variance,
+            samples: dists.len() as u32,
+        })
     }
 
-    /// Spins until the sensor gets a reading that it considers to be a vehicle passing.
-    pub async fn wait_for_trigger(&mut self) -> Result<DistanceReading, SensorError> {
+    /// Spins until the sensor sees a vehicle pass, following it until it leaves view.
+    ///
+    /// Cancel safe, a vehicle partway through passing is resumed on the next call.
+    pub async fn wait_for_trigger(&mut self) -> Result<Detection, SensorError> {
         loop {
-            let reading = self.sensor.get_reading().await?;
-
-            if should_trigger(self.zero, self.threshold, reading.dist) {
-                /* Prevent the sensor from triggering on a slow passing car over and over again by enforcing
-                that each successful trigger must be followed by a 2s period of no triggers before triggering again */
-                let time_since_last_detection = Instant::now().duration_since(self.last_detection);
-                self.last_detection = Instant::now();
-                if time_since_last_detection.as_millis() < 2000 {
-                    log::trace!("Rejecting trigger due to debounce");
-                    continue;
+            let reading = match self.read().await {
+                Ok(reading) => reading,
+                // Don't lose a vehicle that was already seen
+                Err(err) => match self.event.take() {
+                    Some(event) => {
+                        log::warn!(
+                            "Sensor erred while a vehicle passed, ending it early: {}",
+                            err
+                        );
+                        return Ok(event);
+                    }
+                    None => return Err(err),
+                },
+            };
+
+            // Glare and dark paint give garbage distances that could look like a car
+            if !reading.is_valid() {
+                log::trace!(
+                    "Ignoring {:?} reading of {}mm with amp {:?}",
+                    reading.validity,
+                    reading.dist,
+                    reading.amp
+                );
+                continue;
+            }
+
+            let occluded = should_trigger(self.zero, self.threshold, reading.dist);
+
+            match &mut self.event {
+                Some(event) => {
+                    if occluded {
+                        self.last_blocked = Some(reading.instant);
+                    }
+
+                    if event.update(&reading, occluded) {
+                        // Ending without the vehicle leaving means it's parked
+                        self.seen_clear = !occluded;
+                        let event = self.event.take().unwrap();
+                        log::info!(
+                            "Vehicle passed in {:?}, {}mm away at closest",
+                            event.dwell(),
+                            event.min_dist
+                        );
+                        return Ok(event);
+                    }
+                }
+                None if occluded => {
+                    // Prevent a slow or parked car from triggering over and over again
+                    let armed = self.is_armed(reading.instant);
+                    self.last_blocked = Some(reading.instant);
+                    if !armed {
+                        log::trace!("Rejecting trigger due to debounce");
+                        // Wait for this vehicle to leave, rather than catching it part way past
+                        self.seen_clear = false;
+                        self.last_clear = None;
+                        continue;
+                    }
+
+                    log::info!("Triggered!");
+                    self.last_entry = Some(reading.instant);
+                    self.seen_clear = false;
+                    let level = self.zero.saturating_sub(self.threshold);
+                    self.event = Some(Detection::new(
+                        reading,
+                        self.last_clear.take().as_ref(),
+                        level,
+                    ));
+                }
+                None => {
+                    self.seen_clear = true;
+                    self.track_baseline(&reading);
+                    self.last_clear = Some(reading);
                 }
+            }
+        }
+    }
+
+    /// Checks if a vehicle arriving now should be detected.
+    fn is_armed(&self, now: Instant) -> bool {
+        let since = |last: Option<Instant>| last.map_or(Duration::MAX, |last| now - last);
+
+        self.seen_clear
+            && match self.debounce {
+                Debounce::Clear(hold_off) => since(self.last_blocked) >= hold_off,
+                Debounce::MinGap(gap) => since(self.last_entry) >= gap,
+                Debounce::Train => true,
+            }
+    }
+
+    /// Moves the baseline towards a reading of the background.
+    ///
+    /// Readings far off the zero are ignored, so a parked car or person standing in front of the
+    /// sensor can't become the background. The zero also can't drift far from the last zeroing.
+    fn track_baseline(&mut self, reading: &DistanceReading) {
+        let Some(last) = self.last_baseline_reading.replace(reading.instant) else {
+            return;
+        };
+        let settings = self.baseline_settings;
+        if settings.time_constant.is_zero() {
+            return;
+        }
+
+        let near_zero = reading.dist.abs_diff(self.zero) <= self.threshold / 2;
+        // The tail of the car may still be in view
+        let after_detection = self
+            .last_blocked
+            .is_some_and(|last| reading.instant - last < BASELINE_HOLD_OFF);
+        if !near_zero || after_detection {
+            return;
+        }
+
+        let dt = reading.instant.duration_since(last).as_secs_f64();
+        let alpha = (dt / settings.time_constant.as_secs_f64()).min(1.0);
+        let baseline = self.baseline + alpha * (reading.dist as f64 - self.baseline);
 
-                log::info!("Triggered!");
+        let min = self.anchor.saturating_sub(settings.max_drift) as f64;
+        let max = self.anchor.saturating_add(settings.max_drift) as f64;
+        self.baseline = baseline.clamp(min, max);
 
-                return Ok(reading);
+        let zero = self.baseline.round() as u32;
+        if zero != self.zero {
+            if self.baseline == min || self.baseline == max {
+                log::warn!(
+                    "Baseline hit drift limit of {}mm off zero of {}mm, re-zero the sensor",
+                    settings.max_drift,
+                    self.anchor
+                );
             }
+            log::debug!("Baseline moved zero from {}mm to {}mm", self.zero, zero);
+            self.zero = zero;
         }
     }
 }
 
+/// Mean and variance of readings, after trimming the highest and lowest.
+fn trimmed_stats(dists: &mut [u32]) -> (u32, u32) {
+    dists.sort_unstable();
+    let trim = (dists.len() as f64 * ZERO_TRIM) as usize;
+    let kept = &dists[trim..dists.len() - trim];
+
+    let count = kept.len() as f64;
+    let mean = kept.iter().map(|&d| d as f64).sum::<f64>() / count;
+    let variance = kept.iter().map(|&d| (d as f64 - mean).powi(2)).sum::<f64>() / count;
+
+    (mean.round() as u32, variance.round() as u32)
+}
+
 /// Checks if a reading counts as a trigger of the car passing
 fn should_trigger(zero: u32, threshold: u32, reading: u32) -> bool {
     let closer_than_zero = (zero as i64 - reading as i64) > 0;
@@ -79,7 +623,141 @@ fn should_trigger(zero: u32, threshold: u32, reading: u32) -> bool {
 
 #[cfg(test)]
 mod test {
-    use crate::application::should_trigger;
+    use crate::application::{
+        should_trigger, ApplicationContext, BaselineSettings, Debounce, Detection, ZeroSettings,
+    };
+    use crate::dist_sensor::{DistanceReading, DistanceSensor, SensorError};
+    use crate::error::ZeroError;
+    use async_trait::async_trait;
+    use std::time::{Duration, Instant, SystemTime};
+    use tf_luna::types::Validity;
+
+    /// Sensor that repeats a list of readings
+    struct ListSensor(Vec<(u32, Validity)>, usize);
+
+    #[async_trait]
+    impl DistanceSensor for ListSensor {
+        async fn get_reading(&mut self) -> Result<DistanceReading, SensorError> {
+            let (dist, validity) = self.0[self.1 % self.0.len()];
+            self.1 += 1;
+
+            let mut reading = DistanceReading::new(dist);
+            reading.validity = validity;
+            Ok(reading)
+        }
+    }
+
+    /// Sensor that sees 700mm while a car is in view and 1500mm otherwise, reading every 10ms.
+    /// Cars are in view over `[from, to)` in ms. Fails once the script ends.
+    struct ScriptedSensor {
+        start: Instant,
+        cars: Vec<(u64, u64)>,
+        end: u64,
+        now: u64,
+    }
+
+    impl ScriptedSensor {
+        fn new(cars: &[(u64, u64)], end: u64) -> Self {
+            Self {
+                start: Instant::now(),
+                cars: cars.to_vec(),
+                end,
+                now: 0,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl DistanceSensor for ScriptedSensor {
+        async fn get_reading(&mut self) -> Result<DistanceReading, SensorError> {
+            if self.now >= self.end {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+
+            let in_view = self
+                .cars
+                .iter()
+                .any(|&(from, to)| (from..to).contains(&self.now));
+            let dist = if in_view { 700 } else { 1500 };
+            let instant = self.start + Duration::from_millis(self.now);
+            self.now += 10;
+
+            let mut reading = DistanceReading::stamped(dist, instant, SystemTime::now());
+            reading.amp = Some(if in_view { 500 } else { 1000 });
+            Ok(reading)
+        }
+    }
+
+    /// Runs a script through the trigger, giving when each detection arrived in ms.
+    async fn triggers(debounce: Debounce, cars: &[(u64, u64)], end: u64) -> Vec<u64> {
+        let sensor = ScriptedSensor::new(cars, end);
+        let start = sensor.start;
+        let mut app = ApplicationContext::new(
+            sensor,
+            1500,
+            200,
+            debounce,
+            ZeroSettings::default(),
+            BaselineSettings::default(),
+        );
+
+        let mut arrivals = vec![];
+        while let Ok(detection) = app.wait_for_trigger().await {
+            arrivals.push((detection.entry.instant - start).as_millis() as u64);
+        }
+        arrivals
+    }
+
+    #[tokio::test]
+    async fn debounce_modes() {
+        let cars = [(100, 200), (260, 360), (600, 700), (1400, 1500)];
+        let ms = Duration::from_millis;
+
+        // Each car keeps the beam blocked for longer
+        assert_eq!(
+            triggers(Debounce::Clear(ms(500)), &cars, 2000).await,
+            [100, 1400]
+        );
+        // Cars arriving mid gap are ignored until they leave
+        assert_eq!(
+            triggers(Debounce::MinGap(ms(250)), &cars, 2000).await,
+            [100, 600, 1400]
+        );
+        assert_eq!(
+            triggers(Debounce::Train, &cars, 2000).await,
+            [100, 260, 600, 1400]
+        );
+
+        // A parked car is seen once, however long it sits
+        assert_eq!(
+            triggers(Debounce::Train, &[(100, 12_000), (12_500, 12_600)], 13_000).await,
+            [100, 12_500]
+        );
+    }
+
+    #[tokio::test]
+    async fn health_counts_readings() {
+        let mut app = ApplicationContext::new(
+            ScriptedSensor::new(&[(0, 50)], 200),
+            1500,
+            200,
+            Debounce::Train,
+            ZeroSettings::default(),
+            BaselineSettings::default(),
+        );
+        while app.wait_for_trigger().await.is_ok() {}
+
+        let health = app.take_health();
+        assert_eq!(health.mean_amp, Some(875));
+        assert_eq!(health.read_errors, 1);
+        assert_eq!(health.temp_c, None);
+        assert!(health.readings_per_s > 0.0);
+
+        // Rates start over, but totals don't
+        let health = app.take_health();
+        assert_eq!(health.mean_amp, None);
+        assert_eq!(health.read_errors, 1);
+    }
 
     #[test]
     fn should_trigger_works() {
@@ -92,4 +770,157 @@ mod test {
         // Reading less than trigger should trigger
         assert!(should_trigger(8, 1, 1));
     }
+
+    #[tokio::test]
+    async fn zero_rejects_outliers() {
+        // A person walking past, and glare
+        let mut readings = vec![(1500, Validity::Valid); 16];
+        readings.extend([
+            (300, Validity::Valid),
+            (2900, Validity::Valid),
+            (200, Validity::Saturated),
+        ]);
+        let sensor = ListSensor(readings, 0);
+        let mut app = ApplicationContext::new(
+            sensor,
+            10_000,
+            200,
+            Debounce::Train,
+            ZeroSettings::default(),
+            BaselineSettings::default(),
+        );
+
+        let stats = app.zero().await.unwrap();
+        assert_eq!(stats.zero, 1500);
+        assert_eq!(stats.variance, 0);
+    }
+
+    #[tokio::test]
+    async fn bad_zero_keeps_previous() {
+        let sensor = ListSensor(vec![(1490, Validity::Valid), (1510, Validity::Valid)], 0);
+        let mut app = ApplicationContext::new(
+            sensor,
+            10_000,
+            200,
+            Debounce::Train,
+            ZeroSettings::default(),
+            BaselineSettings::default(),
+        );
+        assert_eq!(app.zero().await.unwrap().variance, 100);
+
+        // Nothing in range
+        app.sensor = ListSensor(
+            vec![
+                (0, Validity::Sentinel),
+                (9000, Validity::OutOfRange),
+                (1500, Validity::Valid),
+            ],
+            0,
+        );
+        assert!(matches!(app.zero().await, Err(ZeroError::NoWall(33))));
+
+        // Something moving in front of the sensor
+        app.sensor = ListSensor(vec![(1000, Validity::Valid), (2000, Validity::Valid)], 0);
+        assert!(matches!(app.zero().await, Err(ZeroError::Unstable(_))));
+
+        assert_eq!(app.current_zero(), 1500);
+    }
+
+    #[tokio::test]
+    async fn zero_gives_up_on_dead_sensor() {
+        let mut app = ApplicationContext::new(
+            ScriptedSensor::new(&[], 0),
+            1500,
+            200,
+            Debounce::Train,
+            ZeroSettings {
+                window: Duration::from_secs(60),
+                // Squared, this doesn't fit a u32
+                max_stddev: 70_000,
+            },
+            BaselineSettings::default(),
+        );
+
+        // Rather than erroring for the whole window
+        let res = tokio::time::timeout(Duration::from_secs(1), app.zero()).await;
+        assert!(matches!(res, Ok(Err(ZeroError::NoReadings))));
+        assert_eq!(app.take_health().read_errors, 5);
+
+        app.sensor = ScriptedSensor::new(&[], 1000);
+        assert_eq!(app.zero().await.unwrap().zero, 1500);
+    }
+
+    #[test]
+    fn baseline_tracks_drift() {
+        let mut app = ApplicationContext::new(
+            ListSensor(vec![], 0),
+            1500,
+            200,
+            Debounce::Train,
+            ZeroSettings::default(),
+            BaselineSettings {
+                time_constant: Duration::from_secs(1),
+                max_drift: 100,
+            },
+        );
+        let mut now = Instant::now();
+        let mut feed = |app: &mut ApplicationContext<ListSensor>, dist, secs| {
+            for _ in 0..secs * 10 {
+                now += Duration::from_millis(100);
+                app.track_baseline(&DistanceReading::stamped(dist, now, SystemTime::now()));
+            }
+        };
+
+        // Target slowly heating up
+        feed(&mut app, 1540, 10);
+        assert_eq!(app.current_zero(), 1540);
+
+        // A parked car shouldn't become the background
+        feed(&mut app, 1300, 10);
+        assert_eq!(app.current_zero(), 1540);
+
+        // Nor should the zero wander off
+        for dist in [1580, 1620, 1660] {
+            feed(&mut app, dist, 10);
+        }
+        assert_eq!(app.current_zero(), 1600);
+    }
+
+    #[test]
+    fn detection_spans_gaps() {
+        let start = Instant::now();
+        let reading = |ms, dist| {
+            DistanceReading::stamped(dist, start + Duration::from_millis(ms), SystemTime::now())
+        };
+
+        let mut detection = Detection::new(reading(0, 900), None, 1300);
+        assert!(!detection.update(&reading(10, 700), true));
+        // A window, shorter than needed to end the pass
+        assert!(!detection.update(&reading(20, 1500), false));
+        assert!(!detection.update(&reading(30, 1100), true));
+
+        assert!(!detection.update(&reading(40, 1500), false));
+        assert!(!detection.update(&reading(50, 1500), false));
+        assert!(detection.update(&reading(60, 1500), false));
+
+        assert_eq!(detection.min_dist, 700);
+        assert_eq!(detection.samples, 3);
+        // Left half way between 30 and 40ms
+        assert_eq!(detection.dwell(), Duration::from_millis(35));
+    }
+
+    #[test]
+    fn crossing_interpolated() {
+        let start = Instant::now();
+        let reading = |ms, dist| {
+            DistanceReading::stamped(dist, start + Duration::from_millis(ms), SystemTime::now())
+        };
+
+        let detection = Detection::new(reading(10, 1100), Some(&reading(0, 1500)), 1300);
+        assert_eq!(detection.instant - start, Duration::from_millis(5));
+
+        // Readings too far apart to trust
+        let detection = Detection::new(reading(500, 1200), Some(&reading(0, 1500)), 1300);
+        assert_eq!(detection.instant - start, Duration::from_millis(500));
+    }
 }
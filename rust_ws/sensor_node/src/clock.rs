@@ -0,0 +1,99 @@
+//! Clock synchronisation state, read from chrony.
+//!
+//! Detections are compared between nodes by their stamps, so timing is only as good as the worst
+//! synchronised clock.
+
+use std::io;
+use std::time::Duration;
+use timebay_common::messages::ClockSync;
+use tokio::process::Command;
+
+/// Longest to wait on chronyc, which can hang if chronyd isn't running
+const CHRONYC_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Reads the clock's synchronisation from `chronyc tracking`.
+pub async fn read_tracking() -> io::Result<ClockSync> {
+    let output = tokio::time::timeout(
+        CHRONYC_TIMEOUT,
+        Command::new("chronyc")
+            .args(["-c", "tracking"])
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await
+    .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "chronyc exited with {}",
+            output.status
+        )));
+    }
+
+    let text = String::from_utf8_lossy(&output.stdout);
+    parse_tracking(&text).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Bad chronyc output: {}", text.trim()),
+        )
+    })
+}
+
+/// Parses the CSV output of `chronyc -c tracking`.
+///
+/// Fields are reference id, name, stratum, reference time, system time offset, last offset, RMS
+/// offset, frequency, residual frequency, skew, root delay, root dispersion, update interval, and
+/// leap status. Offsets and delays are in seconds, with the system time offset positive when the
+/// clock is behind.
+fn parse_tracking(text: &str) -> Option<ClockSync> {
+    let fields: Vec<_> = text.trim().split(',').collect();
+    if fields.len() < 14 {
+        return None;
+    }
+
+    let ref_id = u32::from_str_radix(fields[0], 16).ok()?;
+    let stratum = fields[2].parse().ok()?;
+    let behind: f64 = fields[4].parse().ok()?;
+    let root_delay: f64 = fields[10].parse().ok()?;
+    let root_dispersion: f64 = fields[11].parse().ok()?;
+    let leap = fields[13];
+
+    // Bound from the chrony docs, assuming the stratum 1 source is right
+    let error = behind.abs() + root_dispersion + root_delay / 2.0;
+
+    Some(ClockSync {
+        offset_us: (-behind * 1e6).round() as i64,
+        error_us: (error * 1e6).round() as u64,
+        stratum,
+        synced: ref_id != 0 && leap != "Not synchronised",
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::clock::parse_tracking;
+    use timebay_common::messages::ClockSync;
+
+    #[test]
+    fn tracking_parses() {
+        let synced = "C0A80001,gateway,2,1700000000.123456789,0.000150000,0.000020000,\
+                      0.000040000,-12.345,0.001,0.050,0.002000000,0.000500000,64.5,Normal\n";
+        assert_eq!(
+            parse_tracking(synced),
+            Some(ClockSync {
+                offset_us: -150,
+                error_us: 1650,
+                stratum: 2,
+                synced: true,
+            })
+        );
+
+        let unsynced = "00000000,,0,0.000000000,0.000000000,0.000000000,0.000000000,0.000,\
+                        0.000,0.000,1.000000000,1.000000000,0.0,Not synchronised\n";
+        let clock = parse_tracking(unsynced).unwrap();
+        assert!(!clock.synced);
+        assert_eq!(clock.stratum, 0);
+
+        assert_eq!(parse_tracking("506 Cannot talk to daemon"), None);
+    }
+}
@@ -0,0 +1,449 @@
+//! Sensor node configuration, from a TOML file, environment variables, and the command line.
+//!
+//! Settings changed remotely over MQTT take priority over everything else. Then command line
+//! arguments take priority over environment variables, which take priority over the config file.
+//! Anything left unset uses its default.
+
+use crate::application::{BaselineSettings, Debounce, ZeroSettings};
+use crate::error::ConfigError;
+use crate::sensor_connection::SensorBackend;
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tf_luna::Model;
+
+/// Where remote settings are kept if not configured
+const DEFAULT_REMOTE_CONFIG: &str = "sensor_node_remote.toml";
+/// Where unpublished detections are kept if not configured
+const DEFAULT_QUEUE_FILE: &str = "sensor_node_queue.toml";
+
+/// Raw settings, before defaults and validation. Used for both the CLI and config file.
+#[derive(Parser, Deserialize, Debug, Default)]
+#[command(author, version, about = "Timebay sensor node", long_about = None)]
+#[serde(default, deny_unknown_fields)]
+struct Settings {
+    /// TOML file to read settings from. Keys are the long argument names, with underscores.
+    #[arg(short, long, env = "CONFIG")]
+    #[serde(skip)]
+    config: Option<PathBuf>,
+    /// ID of this node. Nodes are passed in ID order. [default: 1]
+    #[arg(short, long, env = "NODE_ID")]
+    node_id: Option<u16>,
+    /// Hostname or IP of the MQTT broker. [default: localhost]
+    #[arg(short, long, env = "BROKER_HOST")]
+    broker_host: Option<String>,
+    /// Port of the MQTT broker. [default: 1883]
+    #[arg(long, env = "BROKER_PORT")]
+    broker_port: Option<u16>,
+    /// Where readings come from, like tfluna, tfluna:/dev/ttyUSB0, mock, pty, or replay:file.csv.
+    /// [default: tfluna]
+    #[arg(short, long, env = "SENSOR")]
+    sensor: Option<String>,
+    /// Model of real sensors, one of tf-luna, tf-mini-plus, tf02-pro, or tf03. [default: tf-luna]
+    #[arg(long, env = "SENSOR_MODEL")]
+    sensor_model: Option<String>,
+    /// File to record all readings to.
+    #[arg(long, env = "RECORD_FILE")]
+    record_file: Option<PathBuf>,
+    /// How much closer than zero a reading must be to trigger, mm. [default: 200]
+    #[arg(short, long, env = "THRESHOLD")]
+    threshold: Option<u32>,
+    /// When to re-arm after a vehicle: clear, once nothing has been in view for debounce_ms; gap,
+    /// debounce_ms after the last vehicle arrived; or train, as soon as each vehicle leaves.
+    /// [default: clear]
+    #[arg(long, env = "DEBOUNCE_MODE")]
+    debounce_mode: Option<String>,
+    /// Hold off time of the debounce mode, ms. [default: 500]
+    #[arg(short, long, env = "DEBOUNCE_MS")]
+    debounce_ms: Option<u64>,
+    /// Time readings are collected over when zeroing, ms. [default: 1000]
+    #[arg(long, env = "ZERO_WINDOW_MS")]
+    zero_window_ms: Option<u64>,
+    /// Largest standard deviation of readings to accept a zero with, mm. [default: 50]
+    #[arg(long, env = "ZERO_MAX_STDDEV")]
+    zero_max_stddev: Option<u32>,
+    /// Time constant of following slow changes in the background, s. 0 disables. [default: 60]
+    #[arg(long, env = "BASELINE_SECS")]
+    baseline_secs: Option<u64>,
+    /// Furthest following the background can move the zero from the last zeroing, mm.
+    /// [default: 100]
+    #[arg(long, env = "BASELINE_MAX_DRIFT")]
+    baseline_max_drift: Option<u32>,
+    /// Zero to use until the sensor is zeroed, mm. [default: 10000]
+    #[arg(long, env = "DEFAULT_ZERO")]
+    default_zero: Option<u32>,
+    /// Time between heartbeat messages, ms. [default: 3000]
+    #[arg(long, env = "HEARTBEAT_MS")]
+    heartbeat_ms: Option<u64>,
+    /// Time between status messages reporting the health of the node, ms. [default: 10000]
+    #[arg(long, env = "STATUS_MS")]
+    status_ms: Option<u64>,
+    /// Sensor frame rate, Hz. [default: left as the sensor has it]
+    #[arg(short, long, env = "FRAME_RATE")]
+    frame_rate: Option<u16>,
+    /// File that settings changed over MQTT are kept in. [default: sensor_node_remote.toml]
+    #[arg(long, env = "REMOTE_CONFIG")]
+    remote_config: Option<PathBuf>,
+    /// File that detections are queued in until published. [default: sensor_node_queue.toml]
+    #[arg(long, env = "QUEUE_FILE")]
+    queue_file: Option<PathBuf>,
+    /// Most detections to queue while disconnected from the broker, dropping the oldest past
+    /// this. [default: 1000]
+    #[arg(long, env = "QUEUE_LEN")]
+    queue_len: Option<usize>,
+}
+
+impl Settings {
+    /// Fills unset settings from other.
+    fn or(self, other: Settings) -> Settings {
+        Settings {
+            config: self.config.or(other.config),
+            node_id: self.node_id.or(other.node_id),
+            broker_host: self.broker_host.or(other.broker_host),
+            broker_port: self.broker_port.or(other.broker_port),
+            sensor: self.sensor.or(other.sensor),
+            sensor_model: self.sensor_model.or(other.sensor_model),
+            record_file: self.record_file.or(other.record_file),
+            threshold: self.threshold.or(other.threshold),
+            debounce_mode: self.debounce_mode.or(other.debounce_mode),
+            debounce_ms: self.debounce_ms.or(other.debounce_ms),
+            zero_window_ms: self.zero_window_ms.or(other.zero_window_ms),
+            zero_max_stddev: self.zero_max_stddev.or(other.zero_max_stddev),
+            baseline_secs: self.baseline_secs.or(other.baseline_secs),
+            baseline_max_drift: self.baseline_max_drift.or(other.baseline_max_drift),
+            default_zero: self.default_zero.or(other.default_zero),
+            heartbeat_ms: self.heartbeat_ms.or(other.heartbeat_ms),
+            status_ms: self.status_ms.or(other.status_ms),
+            frame_rate: self.frame_rate.or(other.frame_rate),
+            remote_config: self.remote_config.or(other.remote_config),
+            queue_file: self.queue_file.or(other.queue_file),
+            queue_len: self.queue_len.or(other.queue_len),
+        }
+    }
+}
+
+impl From<RemoteSettings> for Settings {
+    fn from(remote: RemoteSettings) -> Self {
+        Settings {
+            threshold: remote.threshold,
+            debounce_ms: remote.debounce_ms,
+            frame_rate: remote.frame_rate,
+            ..Default::default()
+        }
+    }
+}
+
+/// Settings changed over MQTT, kept in a file so they survive restarts.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RemoteSettings {
+    pub threshold: Option<u32>,
+    pub debounce_ms: Option<u64>,
+    pub frame_rate: Option<u16>,
+}
+
+impl RemoteSettings {
+    /// Loads remote settings, or none if they were never saved.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Ok(toml::from_str(&text)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(ConfigError::ReadErr(path.into(), err)),
+        }
+    }
+
+    /// Saves remote settings, creating the directory if needed. The old file is only replaced
+    /// once the new one is fully written.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let text =
+            toml::to_string(self).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let tmp = path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(tmp, path)
+    }
+
+    /// Saves the settings that are set in changed over those saved at path.
+    pub fn update(path: &Path, changed: RemoteSettings) -> Result<(), ConfigError> {
+        if changed == RemoteSettings::default() {
+            return Ok(());
+        }
+
+        let saved = Self::load(path)?;
+        let merged = RemoteSettings {
+            threshold: changed.threshold.or(saved.threshold),
+            debounce_ms: changed.debounce_ms.or(saved.debounce_ms),
+            frame_rate: changed.frame_rate.or(saved.frame_rate),
+        };
+        merged
+            .save(path)
+            .map_err(|err| ConfigError::WriteErr(path.into(), err))
+    }
+}
+
+/// Validated sensor node configuration
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub node_id: u16,
+    pub broker_host: String,
+    pub broker_port: u16,
+    pub sensor: SensorBackend,
+    pub sensor_model: Model,
+    pub record_file: Option<PathBuf>,
+    /// Delta off of zero to count as a trigger, mm
+    pub threshold: u32,
+    pub debounce: Debounce,
+    pub zero: ZeroSettings,
+    pub baseline: BaselineSettings,
+    /// Zero used until the sensor is zeroed, mm
+    pub default_zero: u32,
+    pub heartbeat: Duration,
+    pub status_period: Duration,
+    /// Sensor frame rate, Hz. None leaves the sensor as is.
+    pub frame_rate: Option<u16>,
+    pub remote_config: PathBuf,
+    pub queue_file: PathBuf,
+    pub queue_len: usize,
+}
+
+impl Config {
+    /// Loads the config from remote settings, the command line, environment, and config file if
+    /// one is given.
+    ///
+    /// Exits with usage if the command line is invalid.
+    pub fn load() -> Result<Self, ConfigError> {
+        let args = Settings::parse();
+
+        let file = match &args.config {
+            Some(path) => {
+                let text = std::fs::read_to_string(path)
+                    .map_err(|err| ConfigError::ReadErr(path.clone(), err))?;
+                toml::from_str(&text)?
+            }
+            None => Settings::default(),
+        };
+
+        let local = args.or(file);
+        let remote = RemoteSettings::load(
+            local
+                .remote_config
+                .as_deref()
+                .unwrap_or(Path::new(DEFAULT_REMOTE_CONFIG)),
+        )?;
+
+        Self::from_settings(Settings::from(remote).or(local))
+    }
+
+    /// Applies defaults, and checks settings are sensible.
+    fn from_settings(settings: Settings) -> Result<Self, ConfigError> {
+        let config = Self {
+            node_id: settings.node_id.unwrap_or(1),
+            broker_host: settings
+                .broker_host
+                .unwrap_or_else(|| "localhost".to_string()),
+            broker_port: settings.broker_port.unwrap_or(1883),
+            sensor: settings.sensor.as_deref().unwrap_or("tfluna").parse()?,
+            sensor_model: match settings.sensor_model {
+                Some(name) => Model::from_name(&name).ok_or(ConfigError::UnknownModel(name))?,
+                None => Model::default(),
+            },
+            record_file: settings.record_file,
+            threshold: settings.threshold.unwrap_or(200),
+            debounce: {
+                let hold_off = Duration::from_millis(settings.debounce_ms.unwrap_or(500));
+                match settings.debounce_mode.as_deref().unwrap_or("clear") {
+                    "clear" => Debounce::Clear(hold_off),
+                    "gap" => Debounce::MinGap(hold_off),
+                    "train" => Debounce::Train,
+                    _ => {
+                        return Err(ConfigError::Invalid(
+                            "debounce_mode",
+                            "one of clear, gap or train",
+                        ))
+                    }
+                }
+            },
+            zero: ZeroSettings {
+                window: Duration::from_millis(settings.zero_window_ms.unwrap_or(1000)),
+                max_stddev: settings.zero_max_stddev.unwrap_or(50),
+            },
+            baseline: BaselineSettings {
+                time_constant: Duration::from_secs(settings.baseline_secs.unwrap_or(60)),
+                max_drift: settings.baseline_max_drift.unwrap_or(100),
+            },
+            default_zero: settings.default_zero.unwrap_or(10_000),
+            heartbeat: Duration::from_millis(settings.heartbeat_ms.unwrap_or(3000)),
+            status_period: Duration::from_millis(settings.status_ms.unwrap_or(10_000)),
+            frame_rate: settings.frame_rate,
+            remote_config: settings
+                .remote_config
+                .unwrap_or_else(|| DEFAULT_REMOTE_CONFIG.into()),
+            queue_file: settings
+                .queue_file
+                .unwrap_or_else(|| DEFAULT_QUEUE_FILE.into()),
+            queue_len: settings.queue_len.unwrap_or(1000),
+        };
+
+        if config.broker_host.is_empty() {
+            return Err(ConfigError::Invalid("broker_host", "not empty"));
+        }
+        if config.threshold == 0 {
+            return Err(ConfigError::Invalid("threshold", "greater than 0"));
+        }
+        if config.threshold >= config.default_zero {
+            return Err(ConfigError::Invalid("threshold", "less than default_zero"));
+        }
+        if config.baseline.max_drift >= config.default_zero {
+            return Err(ConfigError::Invalid(
+                "baseline_max_drift",
+                "less than default_zero",
+            ));
+        }
+        if config.zero.window.is_zero() {
+            return Err(ConfigError::Invalid("zero_window_ms", "greater than 0"));
+        }
+        if config.heartbeat.is_zero() {
+            return Err(ConfigError::Invalid("heartbeat_ms", "greater than 0"));
+        }
+        if config.status_period.is_zero() {
+            return Err(ConfigError::Invalid("status_ms", "greater than 0"));
+        }
+        if config.queue_len == 0 {
+            return Err(ConfigError::Invalid("queue_len", "greater than 0"));
+        }
+        if config.frame_rate == Some(0) {
+            return Err(ConfigError::Invalid("frame_rate", "greater than 0"));
+        }
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::application::Debounce;
+    use crate::config::{Config, RemoteSettings, Settings};
+    use crate::error::ConfigError;
+    use crate::sensor_connection::SensorBackend;
+    use std::time::Duration;
+    use tf_luna::Model;
+
+    #[test]
+    fn file_merges_under_args() {
+        let file: Settings = toml::from_str(
+            r#"
+            node_id = 3
+            threshold = 500
+            debounce_mode = "gap"
+            debounce_ms = 4000
+            sensor = "mock"
+            sensor_model = "tf02-pro"
+            "#,
+        )
+        .unwrap();
+        let args = Settings {
+            threshold: Some(300),
+            ..Default::default()
+        };
+
+        let config = Config::from_settings(args.or(file)).unwrap();
+        assert_eq!(config.node_id, 3);
+        assert_eq!(config.threshold, 300);
+        assert_eq!(config.debounce, Debounce::MinGap(Duration::from_secs(4)));
+        assert_eq!(config.sensor, SensorBackend::Mock);
+        assert_eq!(config.sensor_model, Model::Tf02Pro);
+        assert_eq!(config.broker_port, 1883);
+    }
+
+    #[test]
+    fn remote_settings_override() {
+        let path = std::env::temp_dir().join(format!(
+            "timebay_remote_config_test_{}.toml",
+            std::process::id()
+        ));
+        let remote = RemoteSettings {
+            threshold: Some(400),
+            frame_rate: Some(200),
+            ..Default::default()
+        };
+        remote.save(&path).unwrap();
+        assert_eq!(RemoteSettings::load(&path).unwrap(), remote);
+
+        // Updates only replace what they set
+        let changed = RemoteSettings {
+            frame_rate: Some(250),
+            ..Default::default()
+        };
+        RemoteSettings::update(&path, changed).unwrap();
+        let remote = RemoteSettings::load(&path).unwrap();
+        assert_eq!(remote.threshold, Some(400));
+        assert_eq!(remote.debounce_ms, None);
+        assert_eq!(remote.frame_rate, Some(250));
+
+        let args = Settings {
+            threshold: Some(300),
+            debounce_ms: Some(1000),
+            ..Default::default()
+        };
+        let config = Config::from_settings(Settings::from(remote).or(args)).unwrap();
+        assert_eq!(config.threshold, 400);
+        assert_eq!(config.debounce, Debounce::Clear(Duration::from_secs(1)));
+        assert_eq!(config.frame_rate, Some(250));
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            RemoteSettings::load(&path).unwrap(),
+            RemoteSettings::default()
+        );
+    }
+
+    #[test]
+    fn bad_settings_rejected() {
+        assert!(toml::from_str::<Settings>("threshhold = 3").is_err());
+
+        let settings = Settings {
+            zero_window_ms: Some(0),
+            ..Default::default()
+        };
+        assert!(matches!(
+            Config::from_settings(settings),
+            Err(ConfigError::Invalid("zero_window_ms", _))
+        ));
+
+        let settings = Settings {
+            baseline_max_drift: Some(u32::MAX),
+            ..Default::default()
+        };
+        assert!(matches!(
+            Config::from_settings(settings),
+            Err(ConfigError::Invalid("baseline_max_drift", _))
+        ));
+
+        let settings = Settings {
+            debounce_mode: Some("never".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            Config::from_settings(settings),
+            Err(ConfigError::Invalid("debounce_mode", _))
+        ));
+
+        let settings = Settings {
+            sensor: Some("sonar".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            Config::from_settings(settings),
+            Err(ConfigError::BadSensorBackend(_))
+        ));
+    }
+}
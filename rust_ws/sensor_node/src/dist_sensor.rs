@@ -4,15 +4,41 @@ use async_trait::async_trait;
 use rand::RngCore;
 use std::cmp::max;
 use std::io;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
+use tf_luna::types::{TimedReading, Validity};
 use tf_luna::TfLuna;
 use thiserror::Error;
 
 /// A sensor capable of reading distance values.
 #[async_trait]
-pub trait DistanceSensor {
+pub trait DistanceSensor: Send {
     /// Gets a distance reading.
     async fn get_reading(&mut self) -> Result<DistanceReading, SensorError>;
+
+    /// Sets how often readings are taken, Hz. Not all sensors support this.
+    async fn set_frame_rate(&mut self, _rate: u16) -> Result<(), SensorError> {
+        Err(SensorError::Unsupported)
+    }
+
+    /// Frames from the sensor that failed their checksum, if it uses them.
+    fn checksum_failures(&self) -> u64 {
+        0
+    }
+}
+
+#[async_trait]
+impl<T: DistanceSensor + Send + ?Sized> DistanceSensor for Box<T> {
+    async fn get_reading(&mut self) -> Result<DistanceReading, SensorError> {
+        (**self).get_reading().await
+    }
+
+    async fn set_frame_rate(&mut self, rate: u16) -> Result<(), SensorError> {
+        (**self).set_frame_rate(rate).await
+    }
+
+    fn checksum_failures(&self) -> u64 {
+        (**self).checksum_failures()
+    }
 }
 
 /// Errors from interacting with the sensor
@@ -22,17 +48,61 @@ pub enum SensorError {
     IOError(#[from] io::Error),
     #[error(transparent)]
     LunaErr(#[from] tf_luna::error::Error),
+    #[error("Operation not supported by this sensor")]
+    Unsupported,
 }
 
 /// A distance reading
+#[derive(Debug, Copy, Clone)]
 pub struct DistanceReading {
     /// Distance reading in mm
     pub dist: u32,
+    /// Monotonic time the reading was taken
+    pub instant: Instant,
+    /// Wall clock time the reading was taken
+    pub stamp: SystemTime,
+    /// Signal strength, if the sensor reports it
+    pub amp: Option<u16>,
+    /// Chip temperature in °C, if the sensor reports it
+    pub temp_c: Option<f32>,
+    /// If the distance can be trusted, or why not
+    pub validity: Validity,
 }
 
 impl DistanceReading {
+    /// Creates a reading taken now.
     pub fn new(dist: u32) -> Self {
-        Self { dist }
+        Self::stamped(dist, Instant::now(), SystemTime::now())
+    }
+
+    /// Creates a reading taken at a known time.
+    pub fn stamped(dist: u32, instant: Instant, stamp: SystemTime) -> Self {
+        Self {
+            dist,
+            instant,
+            stamp,
+            amp: None,
+            temp_c: None,
+            validity: Validity::Valid,
+        }
+    }
+
+    /// If the distance can be trusted for triggering and zeroing
+    pub fn is_valid(&self) -> bool {
+        self.validity == Validity::Valid
+    }
+}
+
+impl From<TimedReading> for DistanceReading {
+    fn from(timed: TimedReading) -> Self {
+        Self {
+            dist: timed.reading.dist_mm(),
+            instant: timed.instant,
+            stamp: timed.time,
+            amp: timed.reading.amp(),
+            temp_c: None,
+            validity: timed.validity,
+        }
     }
 }
 
@@ -63,9 +133,20 @@ impl DistanceSensor for MockDistanceReader {
 #[async_trait]
 impl DistanceSensor for TfLuna {
     async fn get_reading(&mut self) -> Result<DistanceReading, SensorError> {
-        Ok(self
-            .read()
-            .await
-            .map(|x| DistanceReading::new(x.dist as u32 * 10))?)
+        let timed = self.read_timed().await?;
+        let mut reading = DistanceReading::from(timed);
+        // Models without temperature send reserved bytes in its place
+        if self.model().capabilities().has_temp {
+            reading.temp_c = timed.reading.temp_c();
+        }
+        Ok(reading)
+    }
+
+    async fn set_frame_rate(&mut self, rate: u16) -> Result<(), SensorError> {
+        Ok(TfLuna::set_frame_rate(self, rate).await?)
+    }
+
+    fn checksum_failures(&self) -> u64 {
+        self.stats().bad_frames
     }
 }
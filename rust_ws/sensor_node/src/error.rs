@@ -1,4 +1,6 @@
 use crate::dist_sensor::SensorError;
+use std::io;
+use std::path::PathBuf;
 use std::time::SystemTimeError;
 use thiserror::Error;
 
@@ -9,9 +11,49 @@ pub enum Error {
     #[error("MQTT subs are not configured properly")]
     WrongSub,
     #[error("Error during serialization")]
+    #[allow(clippy::enum_variant_names)]
     SerializationError(#[from] timebay_common::error::ConversionError),
     #[error(transparent)]
     SensorErr(#[from] SensorError),
     #[error("System clock rolled back during computation")]
     TimeReset(#[from] SystemTimeError),
 }
+
+/// Reasons zeroing was refused. The previous zero is kept.
+#[derive(Error, Debug)]
+pub enum ZeroError {
+    #[error("No readings from the sensor")]
+    NoReadings,
+    /// Percent of readings that were valid
+    #[error("Only {0}% of readings were valid, as nothing is in range")]
+    NoWall(u32),
+    /// Percent of readings that were valid
+    #[error("Only {0}% of readings were valid, as the signal is too weak or strong")]
+    LowSignal(u32),
+    /// Variance of readings, mm^2
+    #[error("Readings are unstable, with a variance of {0}mm^2")]
+    Unstable(u32),
+}
+
+/// Errors from loading the config
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Failed to read config file {0}: {1}")]
+    ReadErr(PathBuf, io::Error),
+    #[error("Failed to write config file {0}: {1}")]
+    WriteErr(PathBuf, io::Error),
+    #[error("Invalid config file: {0}")]
+    ParseErr(#[from] toml::de::Error),
+    #[error("Unknown sensor backend {0}")]
+    BadSensorBackend(String),
+    #[error("Unknown sensor model {0}")]
+    UnknownModel(String),
+    #[error("Failed to connect to sensor: {0}")]
+    SensorErr(#[from] tf_luna::error::Error),
+    /// File and why it couldn't be opened
+    #[error("Failed to open {0}: {1}")]
+    OpenErr(PathBuf, io::Error),
+    /// Setting and what it must be
+    #[error("{0} must be {1}")]
+    Invalid(&'static str, &'static str),
+}
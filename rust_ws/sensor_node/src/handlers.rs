@@ -1,46 +1,341 @@
-use crate::application::ApplicationContext;
-use crate::dist_sensor::{DistanceReading, DistanceSensor};
-use crate::error::Error;
+use crate::application::{ApplicationContext, Detection};
+use crate::clock;
+use crate::config::{Config, RemoteSettings};
+use crate::dist_sensor::DistanceSensor;
+use crate::error::{Error, ZeroError};
 use crate::mqtt::MqttClient;
-use std::time::{SystemTime, UNIX_EPOCH};
-use timebay_common::messages::{DetectionMessage, MqttMessage};
+use crate::queue::DetectionQueue;
+use std::future::Future;
+use std::pin::pin;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use timebay_common::messages::{
+    DetectionMessage, MqttMessage, NodeConfigMessage, NodeStatusMessage, ZeroResultMessage,
+    ZeroStatus,
+};
+
+/// Wait before reading again after the sensor errs, so an unplugged sensor doesn't spin
+const SENSOR_RETRY_DELAY: Duration = Duration::from_millis(500);
 
 /// Handles an incoming mqtt message
 pub async fn handle_mqtt_msg<T: DistanceSensor>(
     msg: MqttMessage,
-    _client: &mut MqttClient,
+    client: &mut MqttClient,
     ctx: &mut ApplicationContext<T>,
+    config: &Config,
 ) -> Result<(), Error> {
     match msg {
         MqttMessage::Zero => {
             log::trace!("Beginning to zero");
-            ctx.zero().await?;
+
+            // Report failures too, so clients can tell which nodes need attention
+            let node_id = client.node_id();
+            let result = match ctx.zero().await {
+                Ok(stats) => {
+                    ZeroResultMessage::new(node_id, stats.zero, stats.variance, ZeroStatus::Zeroed)
+                }
+                Err(err) => {
+                    log::error!("Refused to zero: {}", err);
+                    let (variance, status) = match err {
+                        ZeroError::NoReadings => (0, ZeroStatus::SensorFailed),
+                        ZeroError::NoWall(_) => (0, ZeroStatus::NoWall),
+                        ZeroError::LowSignal(_) => (0, ZeroStatus::LowSignal),
+                        ZeroError::Unstable(variance) => (variance, ZeroStatus::Unstable),
+                    };
+                    ZeroResultMessage::new(node_id, ctx.current_zero(), variance, status)
+                }
+            };
+
+            client.publish(MqttMessage::ZeroResult(result)).await?;
+            Ok(())
+        }
+        MqttMessage::Config(conf) => {
+            log::info!("Received new config {:?}", conf);
+            let applied = apply_config(ctx, conf).await;
+
+            // Only pin what was changed, so other settings still follow the local config
+            if let Err(err) = RemoteSettings::update(&config.remote_config, applied) {
+                log::error!("Failed to save config with: {}", err);
+            }
+
+            pub_applied_config(client, ctx).await
+        }
+        MqttMessage::ClockPing(ping) => {
+            // Stamped first, as time spent here would look like network delay
+            let received = SystemTime::now();
+            client
+                .publish(MqttMessage::ClockPong(ping.pong(received)))
+                .await?;
             Ok(())
         }
         _ => Err(Error::WrongSub),
     }
 }
 
-/// Handles a trigger
+/// Applies the settings in a config message, skipping any that are invalid. Returns the settings
+/// that were applied.
+async fn apply_config<T: DistanceSensor>(
+    ctx: &mut ApplicationContext<T>,
+    conf: NodeConfigMessage,
+) -> RemoteSettings {
+    let mut applied = RemoteSettings::default();
+
+    // A threshold past the zero could never trigger
+    match conf.threshold {
+        Some(threshold) if threshold == 0 || threshold >= ctx.current_zero() => log::warn!(
+            "Ignoring invalid threshold of {}mm with a zero of {}mm",
+            threshold,
+            ctx.current_zero()
+        ),
+        Some(threshold) => {
+            ctx.set_threshold(threshold);
+            applied.threshold = Some(threshold);
+        }
+        None => {}
+    }
+
+    if let Some(debounce) = conf.debounce_ms {
+        if ctx.set_debounce(Duration::from_millis(debounce)) {
+            applied.debounce_ms = Some(debounce);
+        } else {
+            log::warn!(
+                "Ignoring debounce of {}ms, as the debounce mode has no hold off time",
+                debounce
+            );
+        }
+    }
+
+    if let Some(rate) = conf.frame_rate {
+        match ctx.set_frame_rate(rate).await {
+            Ok(_) => applied.frame_rate = Some(rate),
+            Err(err) => log::warn!("Failed to set frame rate to {}Hz: {}", rate, err),
+        }
+    }
+
+    applied
+}
+
+/// Publishes the settings the node is using.
+pub async fn pub_applied_config<T: DistanceSensor>(
+    client: &mut MqttClient,
+    ctx: &ApplicationContext<T>,
+) -> Result<(), Error> {
+    let msg = MqttMessage::ConfigApplied(NodeConfigMessage::new(
+        client.node_id(),
+        Some(ctx.threshold()),
+        Some(ctx.debounce().as_millis() as u64),
+        ctx.frame_rate(),
+    ));
+
+    client.publish(msg).await?;
+
+    Ok(())
+}
+
+/// Publishes the health of the node, with rates since the last status.
+pub async fn pub_status<T: DistanceSensor>(
+    client: &mut MqttClient,
+    ctx: &mut ApplicationContext<T>,
+    uptime: Duration,
+) -> Result<(), Error> {
+    let health = ctx.take_health();
+    // Not having chrony, like when developing, isn't worth more than a debug log
+    let clock = match clock::read_tracking().await {
+        Ok(clock) => Some(clock),
+        Err(err) => {
+            log::debug!("Failed to read clock sync with: {}", err);
+            None
+        }
+    };
+
+    let msg = MqttMessage::Status(NodeStatusMessage {
+        node_id: client.node_id(),
+        uptime_s: uptime.as_secs(),
+        temp_c: health.temp_c.map(|temp| temp.round() as i16),
+        mean_amp: health.mean_amp,
+        zero: ctx.current_zero(),
+        threshold: ctx.threshold(),
+        readings_per_s: health.readings_per_s.round() as u32,
+        checksum_failures: health.checksum_failures,
+        read_errors: health.read_errors,
+        reconnects: client.reconnects(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        clock,
+    });
+
+    client.publish(msg).await?;
+
+    Ok(())
+}
+
+/// Handles a vehicle passing, queueing the detection so it isn't lost if publishing fails
 pub async fn handle_trigger<T: DistanceSensor>(
     client: &mut MqttClient,
     _ctx: &mut ApplicationContext<T>,
-    dist: DistanceReading,
+    queue: &mut DetectionQueue,
+    detection: Detection,
 ) -> Result<(), Error> {
     log::trace!("Handling trigger");
 
-    // Publish a detection message
-    let now = SystemTime::now()
+    queue.push(detection_msg(client.node_id(), &detection));
+    pub_queued(client, queue).await
+}
+
+/// Keeps detecting vehicles until reconnected completes, queueing detections to publish after.
+pub async fn queue_until<T: DistanceSensor>(
+    ctx: &mut ApplicationContext<T>,
+    queue: &mut DetectionQueue,
+    node_id: u16,
+    reconnected: impl Future<Output = ()>,
+) {
+    let mut reconnected = pin!(reconnected);
+
+    loop {
+        tokio::select! {
+            _ = &mut reconnected => return,
+            res = ctx.wait_for_trigger() => match res {
+                Ok(detection) => {
+                    queue.push(detection_msg(node_id, &detection));
+                    log::info!("Queued detection while disconnected, {} queued", queue.len());
+
+                    // Kept on disk, as the outage may outlast the node
+                    if let Err(err) = queue.save() {
+                        log::error!("Failed to save detection queue with: {}", err);
+                    }
+                }
+                Err(err) => {
+                    log::error!("Sensor erred while reading: {}", err);
+                    tokio::time::sleep(SENSOR_RETRY_DELAY).await;
+                }
+            }
+        }
+    }
+}
+
+/// Publishes queued detections in order, stopping at the first that fails.
+pub async fn pub_queued(client: &mut MqttClient, queue: &mut DetectionQueue) -> Result<(), Error> {
+    if queue.len() > 1 {
+        log::info!("Publishing {} queued detections", queue.len());
+    }
+
+    let mut res = Ok(());
+    while let Some(msg) = queue.front() {
+        if let Err(err) = client.publish(MqttMessage::Detection(*msg)).await {
+            res = Err(err.into());
+            break;
+        }
+        queue.pop();
+    }
+
+    // Saved once rather than per detection. Detections republished after a crash are dropped
+    // by their sequence numbers.
+    if let Err(err) = queue.save() {
+        log::error!("Failed to save detection queue with: {}", err);
+    }
+
+    res
+}
+
+/// Creates the message for a vehicle passing, stamped with when it actually crossed the threshold.
+fn detection_msg(node_id: u16, detection: &Detection) -> DetectionMessage {
+    let stamp = detection
+        .stamp
         .duration_since(UNIX_EPOCH)
         .expect("Should never be earlier than epoch");
-    let msg = MqttMessage::Detection(DetectionMessage::new(
-        client.node_id(),
-        dist.dist,
-        now.as_secs(),
-        now.subsec_nanos(),
-    ));
 
-    client.publish(msg).await?;
+    DetectionMessage::new(
+        node_id,
+        detection.entry.dist,
+        stamp.as_secs(),
+        stamp.subsec_nanos(),
+    )
+    .with_exit(detection.exit_stamp, detection.min_dist, detection.samples)
+}
 
-    Ok(())
+#[cfg(test)]
+mod test {
+    use crate::application::{ApplicationContext, BaselineSettings, Debounce, ZeroSettings};
+    use crate::handlers::{apply_config, queue_until};
+    use crate::queue::DetectionQueue;
+    use crate::recording::Replay;
+    use std::io::Write;
+    use std::time::Duration;
+    use timebay_common::messages::NodeConfigMessage;
+
+    #[tokio::test]
+    async fn detections_queued_while_disconnected() {
+        let dir = std::env::temp_dir();
+        let recording = dir.join(format!("timebay_offline_test_{}.csv", std::process::id()));
+        let queue_file = dir.join(format!("timebay_offline_test_{}.toml", std::process::id()));
+
+        // Two cars pass
+        let mut file = std::fs::File::create(&recording).unwrap();
+        let dists = [
+            1500, 1500, 700, 700, 1500, 1500, 1500, 700, 1500, 1500, 1500,
+        ];
+        for (i, dist) in dists.iter().enumerate() {
+            writeln!(
+                file,
+                "{},{},1000,Valid",
+                1_000_000_000 + i * 10_000_000,
+                dist
+            )
+            .unwrap();
+        }
+        drop(file);
+
+        let mut app = ApplicationContext::new(
+            Replay::open(&recording, f64::INFINITY).unwrap(),
+            1500,
+            200,
+            Debounce::Train,
+            ZeroSettings::default(),
+            BaselineSettings::default(),
+        );
+        let mut queue = DetectionQueue::new(&queue_file, 10);
+
+        // Broker comes back after the recording has run
+        let broker_back = tokio::time::sleep(Duration::from_millis(200));
+        queue_until(&mut app, &mut queue, 3, broker_back).await;
+
+        assert_eq!(queue.len(), 2);
+        let queue = DetectionQueue::open(&queue_file, 10).unwrap();
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.front().unwrap().node_id, 3);
+        assert_eq!(queue.front().unwrap().seq, 0);
+
+        std::fs::remove_file(recording).unwrap();
+        std::fs::remove_file(queue_file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn only_applied_settings_returned() {
+        let recording =
+            std::env::temp_dir().join(format!("timebay_apply_test_{}.csv", std::process::id()));
+        std::fs::write(&recording, "1000000000,1500,1000,Valid\n").unwrap();
+
+        let mut app = ApplicationContext::new(
+            Replay::open(&recording, f64::INFINITY).unwrap(),
+            1500,
+            200,
+            Debounce::Train,
+            ZeroSettings::default(),
+            BaselineSettings::default(),
+        );
+
+        // Train mode has no hold off time to set, and the threshold is past the zero
+        let applied = apply_config(
+            &mut app,
+            NodeConfigMessage::new(3, Some(1600), Some(300), None),
+        )
+        .await;
+        assert_eq!(applied.debounce_ms, None);
+        assert_eq!(applied.threshold, None);
+        assert_eq!(app.threshold(), 200);
+
+        let applied =
+            apply_config(&mut app, NodeConfigMessage::new(3, Some(300), None, None)).await;
+        assert_eq!(applied.threshold, Some(300));
+
+        std::fs::remove_file(recording).unwrap();
+    }
 }
@@ -1,20 +1,41 @@
 mod application;
+mod clock;
+mod config;
 mod dist_sensor;
 mod error;
 mod handlers;
 mod mqtt;
+mod queue;
+mod recording;
 mod sensor_connection;
 
 use crate::application::ApplicationContext;
-use crate::handlers::{handle_mqtt_msg, handle_trigger};
+use crate::config::Config;
+use crate::error::ConfigError;
+use crate::handlers::{
+    handle_mqtt_msg, handle_trigger, pub_applied_config, pub_queued, pub_status, queue_until,
+};
 use crate::mqtt::MqttClient;
+use crate::queue::DetectionQueue;
 use log::LevelFilter::Trace;
 use simplelog::{ColorChoice, CombinedLogger, TerminalMode};
-use std::time::Duration;
-use tokio::join;
+use std::time::{Duration, Instant};
+use tokio::try_join;
+
+/// Times to try zeroing on startup before running with the default zero
+const ZERO_ATTEMPTS: u32 = 5;
+
+/// Exits on a config that can't be run with.
+fn exit_invalid_config(err: ConfigError) -> ! {
+    eprintln!("Invalid config: {}", err);
+    std::process::exit(1);
+}
 
 #[tokio::main]
 async fn main() {
+    let started = Instant::now();
+    let config = Config::load().unwrap_or_else(|err| exit_invalid_config(err));
+
     CombinedLogger::init(vec![simplelog::TermLogger::new(
         Trace,
         simplelog::ConfigBuilder::default()
@@ -25,29 +46,42 @@ async fn main() {
     )])
     .unwrap();
 
+    // Detections that weren't published before a restart are sent once connected
+    let mut queue =
+        DetectionQueue::open(&config.queue_file, config.queue_len).unwrap_or_else(|err| {
+            log::error!(
+                "Failed to open detection queue {}, starting empty: {}",
+                config.queue_file.display(),
+                err
+            );
+            DetectionQueue::new(&config.queue_file, config.queue_len)
+        });
+    log::info!(
+        "Starting with boot id {} and {} detections queued",
+        queue.boot_id(),
+        queue.len()
+    );
+
     log::info!("Waiting for mqtt and sensor to connect...");
 
     let (sensor, mut client) = {
-        // Get broker and node id from env vars
-        let node_id = std::env::var("NODE_ID")
-            .unwrap_or_else(|_| "1".to_string())
-            .parse()
-            .unwrap();
-        let server_host = std::env::var("BROKER_HOST").unwrap_or_else(|_| "localhost".to_string());
-
         log::info!(
             "Using a client id {} and broker ip {}",
-            node_id,
-            server_host
+            config.node_id,
+            config.broker_host
         );
 
-        let sensor_fut = sensor_connection::create_sensor();
+        let sensor_fut = sensor_connection::create_sensor(
+            config.sensor.clone(),
+            config.sensor_model,
+            config.record_file.as_deref(),
+        );
 
         // Future that keeps polling until we connect to mqtt
+        let server_uri = format!("mqtt://{}:{}", config.broker_host, config.broker_port);
         let client_fut = async {
             loop {
-                let res =
-                    MqttClient::connect(node_id, &format!("mqtt://{}:1883", &server_host)).await;
+                let res = MqttClient::connect(config.node_id, &server_uri).await;
                 if let Ok(conn) = res {
                     log::info!("Successfully connected to broker");
                     break conn;
@@ -58,38 +92,85 @@ async fn main() {
                 }
             }
         };
-        join!(sensor_fut, client_fut)
+        // Don't wait on the broker if the sensor can never be used
+        let client_fut = async { Ok(client_fut.await) };
+        try_join!(sensor_fut, client_fut).unwrap_or_else(|err| exit_invalid_config(err))
     };
     log::info!("Sensor and mqtt both ready!");
 
     // Zero sensor
-    let mut app = ApplicationContext::new(sensor, 10_000, 200);
+    let mut app = ApplicationContext::new(
+        sensor,
+        config.default_zero,
+        config.threshold,
+        config.debounce,
+        config.zero,
+        config.baseline,
+    );
+
+    if let Some(rate) = config.frame_rate {
+        if let Err(err) = app.set_frame_rate(rate).await {
+            log::error!("Failed to set frame rate to {}Hz: {}", rate, err);
+        }
+    }
 
-    while let Err(err) = app.zero().await {
-        log::error!("Failed to zero sensor with: {:?}", err);
+    // Give up eventually, since the sensor can be re-zeroed once things are fixed
+    for _ in 0..ZERO_ATTEMPTS {
+        match app.zero().await {
+            Ok(_) => break,
+            Err(err) => log::error!("Failed to zero sensor with: {}", err),
+        }
+    }
+
+    // Let clients know what settings we are running with
+    if let Err(err) = pub_applied_config(&mut client, &app).await {
+        log::error!("Failed to publish config with: {}", err);
     }
 
+    // Catch up on detections from before a restart
     let mut disconnected = false;
+    if let Err(err) = pub_queued(&mut client, &mut queue).await {
+        disconnected = true;
+        log::error!("Failed to publish queued detections with: {}", err);
+    }
+
+    let mut last_status = Instant::now();
     loop {
         // Attempt reconnect on disconnect
         if disconnected {
             log::error!("Disconnected from broker!");
-            client.reconnect().await;
+            // Vehicles still pass while the broker is away, so keep detecting
+            queue_until(&mut app, &mut queue, config.node_id, client.reconnect()).await;
             log::info!("Reconnected to broker!");
             disconnected = false;
+
+            // Catch up on detections made while disconnected
+            if let Err(err) = pub_queued(&mut client, &mut queue).await {
+                disconnected = true;
+                log::error!("Failed to publish queued detections with: {}", err);
+                continue;
+            }
         }
 
         // Send connected messages as a sort of heartbeat, allowing for late connecting clients to discover us
-        let res = client.pub_connected_msg().await;
-        if res.is_err() {
+        if let Err(err) = client.pub_connected_msg().await {
             disconnected = true;
-            log::error!("Failed connect heartbeat with: {}", res.unwrap_err());
+            log::error!("Failed connect heartbeat with: {}", err);
             continue;
         }
 
+        if last_status.elapsed() >= config.status_period {
+            if let Err(err) = pub_status(&mut client, &mut app, started.elapsed()).await {
+                disconnected = true;
+                log::error!("Failed to publish status with: {}", err);
+                continue;
+            }
+            last_status = Instant::now();
+        }
+
         let rcv_fut = client.recv_mqtt_msg();
         let trg_fut = app.wait_for_trigger();
-        let timeout = tokio::time::sleep(Duration::from_secs(3));
+        let timeout = tokio::time::sleep(config.heartbeat);
 
         // Accept new messages and wait for sensor concurrently (branches are mutually exclusive)
         tokio::select! {
@@ -100,13 +181,11 @@ async fn main() {
                 }
 
                 let msg = res.unwrap();
-                let res = handle_mqtt_msg(msg, &mut client, &mut app).await;
+                let res = handle_mqtt_msg(msg, &mut client, &mut app, &config).await;
                 if let Err(err) = res {
                     match err {
                         error::Error::SensorErr(err) => {
-                            if let dist_sensor::SensorError::LunaErr(tf_luna::error::Error::ChecksumFailed) = err {
-                                log::error!("Checksum Failed!")
-                            }
+                            log::error!("Sensor erred while handling message: {}", err)
                         }
                         _ => disconnected = true
                     }
@@ -117,7 +196,8 @@ async fn main() {
                     log::error!("Sensor erred while reading: {}", err);
                     continue
                 }
-                if handle_trigger(&mut client, &mut app, res.unwrap()).await.is_err() {
+                if let Err(err) = handle_trigger(&mut client, &mut app, &mut queue, res.unwrap()).await {
+                    log::error!("Failed to publish detection, {} queued: {}", queue.len(), err);
                     disconnected = true;
                 }
             },
@@ -4,7 +4,9 @@ use std::time::Duration;
 use paho_mqtt::ConnectOptionsBuilder;
 
 use timebay_common::messages::MqttMessage::{Connection, Disconnection};
-use timebay_common::messages::{ConnectionMessage, DisconnectionMessage};
+use timebay_common::messages::{
+    clock_ping_topic, config_topic, ConnectionMessage, DisconnectionMessage,
+};
 
 use crate::error::Error;
 
@@ -12,6 +14,7 @@ use crate::error::Error;
 pub struct MqttClient {
     cli: timebay_common::mqttclient::MqttClient,
     node_id: u16,
+    reconnects: u32,
 }
 
 // Deref to client to emulate "inheritance"
@@ -32,7 +35,9 @@ impl DerefMut for MqttClient {
 impl MqttClient {
     pub async fn connect(node_id: u16, server_id: &str) -> Result<Self, Error> {
         // Topics to sub to
-        let subs = ["/zero"];
+        let config_topic = config_topic(node_id);
+        let ping_topic = clock_ping_topic(node_id);
+        let subs = ["/zero", config_topic.as_str(), ping_topic.as_str()];
 
         // Set LWT
         let mut conn_opt = ConnectOptionsBuilder::default();
@@ -55,15 +60,26 @@ impl MqttClient {
         let msg = Connection(ConnectionMessage::new(node_id));
         cli.publish(msg).await?;
 
-        Ok(Self { cli, node_id })
+        Ok(Self {
+            cli,
+            node_id,
+            reconnects: 0,
+        })
     }
 
     pub fn node_id(&self) -> u16 {
         self.node_id
     }
 
+    /// Times the client has reconnected to the broker.
+    pub fn reconnects(&self) -> u32 {
+        self.reconnects
+    }
+
     /// Reconnects to the broker if disconnected, publishing a connected message if so.
-    pub async fn reconnect(&self) {
+    pub async fn reconnect(&mut self) {
+        self.reconnects += 1;
+
         loop {
             while let Err(err) = self.cli.reconnect().await {
                 log::error!("Erred with {} during reconnect attempt!", err);
@@ -0,0 +1,167 @@
+//! Detections waiting to be published, kept on disk so they survive broker disconnects and
+//! restarts.
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use timebay_common::messages::DetectionMessage;
+
+/// Contents of the queue file
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+struct Queued {
+    detections: VecDeque<DetectionMessage>,
+}
+
+/// Bounded queue of detections, oldest first. Once full, the oldest are dropped.
+pub struct DetectionQueue {
+    path: PathBuf,
+    capacity: usize,
+    /// Random id of this run of the node, so consumers can tell restarts from repeats
+    boot_id: u32,
+    /// Sequence number of the next detection, counting from 0 each boot
+    next_seq: u32,
+    queued: Queued,
+    /// If the queue changed since it was last saved
+    dirty: bool,
+}
+
+impl DetectionQueue {
+    /// Opens the queue kept at path, or an empty one if there is none.
+    pub fn open(path: &Path, capacity: usize) -> io::Result<Self> {
+        let queued = match std::fs::read_to_string(path) {
+            Ok(text) => toml::from_str(&text)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Queued::default(),
+            Err(err) => return Err(err),
+        };
+
+        Ok(Self {
+            path: path.into(),
+            capacity,
+            boot_id: rand::random(),
+            next_seq: 0,
+            queued,
+            dirty: false,
+        })
+    }
+
+    /// Creates an empty queue, replacing any kept at path once saved.
+    pub fn new(path: &Path, capacity: usize) -> Self {
+        Self {
+            path: path.into(),
+            capacity,
+            boot_id: rand::random(),
+            next_seq: 0,
+            queued: Queued::default(),
+            dirty: true,
+        }
+    }
+
+    /// Random id of this run of the node
+    pub fn boot_id(&self) -> u32 {
+        self.boot_id
+    }
+
+    /// Detections waiting to be published
+    pub fn len(&self) -> usize {
+        self.queued.detections.len()
+    }
+
+    /// Oldest detection waiting to be published
+    pub fn front(&self) -> Option<&DetectionMessage> {
+        self.queued.detections.front()
+    }
+
+    /// Numbers a detection and adds it to the back of the queue. Call [`DetectionQueue::save`] to
+    /// keep it on disk.
+    pub fn push(&mut self, detection: DetectionMessage) {
+        let detection = detection.with_seq(self.boot_id, self.next_seq);
+        self.next_seq = self.next_seq.wrapping_add(1);
+        self.queued.detections.push_back(detection);
+
+        if self.len() > self.capacity {
+            let dropped = self.queued.detections.pop_front().unwrap();
+            log::warn!(
+                "Detection queue full, dropping detection {} from {}.{}",
+                dropped.seq,
+                dropped.stamp_s,
+                dropped.stamp_ns
+            );
+        }
+        self.dirty = true;
+    }
+
+    /// Removes the oldest detection, once it has been published.
+    pub fn pop(&mut self) {
+        self.queued.detections.pop_front();
+        self.dirty = true;
+    }
+
+    /// Writes the queue to disk if it changed, replacing the old file only once fully written.
+    pub fn save(&mut self) -> io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let text = toml::to_string(&self.queued)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let tmp = self.path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(tmp, &self.path)?;
+
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::queue::DetectionQueue;
+    use timebay_common::messages::DetectionMessage;
+
+    #[test]
+    fn queue_survives_restart() {
+        let path =
+            std::env::temp_dir().join(format!("timebay_queue_test_{}.toml", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut queue = DetectionQueue::open(&path, 2).unwrap();
+        for stamp_s in 1..=3 {
+            queue.push(DetectionMessage::new(1, 700, stamp_s, 0));
+        }
+        queue.save().unwrap();
+        // Oldest was dropped to stay bounded
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.front().unwrap().stamp_s, 2);
+
+        let boot_id = queue.front().unwrap().boot_id;
+        assert_eq!(boot_id, queue.boot_id());
+
+        // Detections keep the boot they were made in
+        let mut queue = DetectionQueue::open(&path, 2).unwrap();
+        assert_eq!(queue.front().unwrap().boot_id, boot_id);
+        assert_eq!(queue.front().unwrap().seq, 1);
+        queue.pop();
+        assert_eq!(queue.front().unwrap().seq, 2);
+        queue.pop();
+        assert!(queue.front().is_none());
+        queue.save().unwrap();
+
+        // Numbering starts over after a restart, under a new boot
+        let mut queue = DetectionQueue::open(&path, 2).unwrap();
+        queue.push(DetectionMessage::new(1, 700, 4, 0));
+        assert_eq!(queue.front().unwrap().seq, 0);
+        assert_ne!(queue.front().unwrap().boot_id, boot_id);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,273 @@
+//! Recording readings to a file, and replaying them back as a sensor.
+//!
+//! Recordings are CSV, with a line of `stamp_ns,dist,amp,validity` per reading. `stamp_ns` is wall
+//! clock time since the unix epoch, and `amp` is empty if the sensor does not report it.
+
+use crate::dist_sensor::{DistanceReading, DistanceSensor, SensorError};
+use async_trait::async_trait;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tf_luna::types::Validity;
+
+/// Header line of recordings
+const HEADER: &str = "stamp_ns,dist,amp,validity";
+/// How often recordings are flushed, so a crash loses little
+const FLUSH_PERIOD: Duration = Duration::from_secs(1);
+
+/// A reading as stored in a recording
+#[derive(Copy, Clone)]
+struct Recorded {
+    stamp: SystemTime,
+    dist: u32,
+    amp: Option<u16>,
+    validity: Validity,
+}
+
+/// Wraps a sensor, recording every reading it takes to a file.
+///
+/// Recording stops if the file can't be written, like when the disk fills, but readings continue.
+pub struct Recorder<T> {
+    sensor: T,
+    /// None once recording has failed
+    out: Option<BufWriter<File>>,
+    last_flush: Instant,
+}
+
+impl<T: DistanceSensor> Recorder<T> {
+    /// Starts recording readings from sensor to a new file at path, overwriting any existing one.
+    pub fn create(sensor: T, path: &Path) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "{}", HEADER)?;
+
+        Ok(Self {
+            sensor,
+            out: Some(out),
+            last_flush: Instant::now(),
+        })
+    }
+
+    /// Writes a reading to the recording, flushing periodically.
+    fn record(&mut self, reading: &DistanceReading) -> io::Result<()> {
+        let Some(out) = self.out.as_mut() else {
+            return Ok(());
+        };
+
+        let stamp = reading
+            .stamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let amp = reading.amp.map(|amp| amp.to_string()).unwrap_or_default();
+        writeln!(
+            out,
+            "{},{},{},{:?}",
+            stamp, reading.dist, amp, reading.validity
+        )?;
+
+        if self.last_flush.elapsed() > FLUSH_PERIOD {
+            out.flush()?;
+            self.last_flush = Instant::now();
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T: DistanceSensor + Send> DistanceSensor for Recorder<T> {
+    async fn get_reading(&mut self) -> Result<DistanceReading, SensorError> {
+        let reading = self.sensor.get_reading().await?;
+
+        // Recording is only for debugging, so it shouldn't stop detections
+        if let Err(err) = self.record(&reading) {
+            log::error!("Failed to record reading, recording stopped: {}", err);
+            self.out = None;
+        }
+
+        Ok(reading)
+    }
+
+    async fn set_frame_rate(&mut self, rate: u16) -> Result<(), SensorError> {
+        self.sensor.set_frame_rate(rate).await
+    }
+
+    fn checksum_failures(&self) -> u64 {
+        self.sensor.checksum_failures()
+    }
+}
+
+/// Replays a recording as a sensor, at the speed it was recorded or faster.
+///
+/// Reading instants keep the recorded spacing regardless of speed, so debouncing behaves as it did
+/// live. Once the recording runs out, readings never return.
+pub struct Replay {
+    lines: Lines<BufReader<File>>,
+    speed: f64,
+    /// Reading read from the file but not yet returned, kept in case get_reading is cancelled
+    pending: Option<Recorded>,
+    /// If the recording has run out
+    finished: bool,
+    /// Recorded stamp of the first reading, and when it was replayed
+    start: Option<(SystemTime, Instant)>,
+}
+
+impl Replay {
+    /// Opens a recording to replay at speed times real time. Use [`f64::INFINITY`] to replay as fast
+    /// as possible.
+    pub fn open(path: &Path, speed: f64) -> io::Result<Self> {
+        Ok(Self {
+            lines: BufReader::new(File::open(path)?).lines(),
+            speed,
+            pending: None,
+            finished: false,
+            start: None,
+        })
+    }
+
+    /// Reads the next reading from the file, or None at the end.
+    fn next_line(&mut self) -> io::Result<Option<Recorded>> {
+        loop {
+            let Some(line) = self.lines.next().transpose()? else {
+                return Ok(None);
+            };
+            if line == HEADER || line.trim().is_empty() {
+                continue;
+            }
+
+            return parse_line(&line).map(Some).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Bad recording line: {}", line),
+                )
+            });
+        }
+    }
+}
+
+#[async_trait]
+impl DistanceSensor for Replay {
+    async fn get_reading(&mut self) -> Result<DistanceReading, SensorError> {
+        let line = match self.pending {
+            Some(line) => line,
+            None => match self.next_line()? {
+                Some(line) => *self.pending.insert(line),
+                None => {
+                    if !self.finished {
+                        log::info!("Replay finished");
+                        self.finished = true;
+                    }
+                    return std::future::pending().await;
+                }
+            },
+        };
+
+        let (first, started) = *self.start.get_or_insert((line.stamp, Instant::now()));
+        let offset = line.stamp.duration_since(first).unwrap_or_default();
+
+        tokio::time::sleep_until((started + offset.div_f64(self.speed)).into()).await;
+        self.pending = None;
+
+        let mut reading = DistanceReading::stamped(line.dist, started + offset, line.stamp);
+        reading.amp = line.amp;
+        reading.validity = line.validity;
+        Ok(reading)
+    }
+}
+
+/// Parses a reading line of a recording.
+fn parse_line(line: &str) -> Option<Recorded> {
+    let mut fields = line.split(',');
+
+    let stamp = UNIX_EPOCH + Duration::from_nanos(fields.next()?.parse().ok()?);
+    let dist = fields.next()?.parse().ok()?;
+    let amp = match fields.next()? {
+        "" => None,
+        amp => Some(amp.parse().ok()?),
+    };
+    let validity = match fields.next()? {
+        "Valid" => Validity::Valid,
+        "LowAmp" => Validity::LowAmp,
+        "Saturated" => Validity::Saturated,
+        "OutOfRange" => Validity::OutOfRange,
+        "Sentinel" => Validity::Sentinel,
+        _ => return None,
+    };
+
+    Some(Recorded {
+        stamp,
+        dist,
+        amp,
+        validity,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::application::{ApplicationContext, BaselineSettings, Debounce, ZeroSettings};
+    use crate::dist_sensor::DistanceSensor;
+    use crate::recording::Replay;
+    use std::io::Write;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn replays_into_trigger() {
+        let path =
+            std::env::temp_dir().join(format!("timebay_replay_test_{}.csv", std::process::id()));
+        let mut file = std::fs::File::create(&path).unwrap();
+        writeln!(file, "stamp_ns,dist,amp,validity").unwrap();
+        writeln!(file, "3000000000,1500,1000,Valid").unwrap();
+        // Glare shouldn't trigger
+        writeln!(file, "3010000000,200,65535,Saturated").unwrap();
+        writeln!(file, "6000000000,700,800,Valid").unwrap();
+        writeln!(file, "6010000000,1100,800,Valid").unwrap();
+        for stamp in ["6020000000", "6030000000", "6040000000"] {
+            writeln!(file, "{},1500,1000,Valid", stamp).unwrap();
+        }
+        drop(file);
+
+        let replay = Replay::open(&path, f64::INFINITY).unwrap();
+        let mut app = ApplicationContext::new(
+            replay,
+            1500,
+            200,
+            Debounce::Clear(Duration::from_secs(2)),
+            ZeroSettings::default(),
+            BaselineSettings::default(),
+        );
+
+        let detection = app.wait_for_trigger().await.unwrap();
+        assert_eq!(detection.entry.dist, 700);
+        assert_eq!(detection.entry.amp, Some(800));
+        assert_eq!(detection.min_dist, 700);
+        assert_eq!(detection.samples, 2);
+        // Left half way between the last two readings
+        assert_eq!(detection.dwell(), Duration::from_millis(15));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn cancelled_reading_not_lost() {
+        let path = std::env::temp_dir().join(format!(
+            "timebay_replay_cancel_test_{}.csv",
+            std::process::id()
+        ));
+        let mut file = std::fs::File::create(&path).unwrap();
+        writeln!(file, "1000000000,1500,1000,Valid").unwrap();
+        writeln!(file, "1100000000,700,1000,Valid").unwrap();
+        drop(file);
+
+        let mut replay = Replay::open(&path, 1.0).unwrap();
+        assert_eq!(replay.get_reading().await.unwrap().dist, 1500);
+
+        // Cancelled while waiting for the next reading, like on a heartbeat
+        let cancelled = tokio::time::timeout(Duration::from_millis(20), replay.get_reading()).await;
+        assert!(cancelled.is_err());
+        assert_eq!(replay.get_reading().await.unwrap().dist, 700);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
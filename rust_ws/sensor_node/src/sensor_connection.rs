@@ -1,23 +1,177 @@
-use crate::dist_sensor::{DistanceSensor, MockDistanceReader};
-use std::path::PathBuf;
-use tf_luna::TfLuna;
-
-/// Connects to the distance sensor
-pub async fn create_sensor() -> impl DistanceSensor {
-    #[cfg(feature = "no_sensor")]
-    {
-        log::trace!("Creating fake sensor");
-        MockDistanceReader::new(10, 12000)
+use crate::dist_sensor::{DistanceReading, DistanceSensor, MockDistanceReader, SensorError};
+use crate::error::ConfigError;
+use crate::recording::{Recorder, Replay};
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tf_luna::{Model, TfLuna};
+use tf_luna_emu::Emulator;
+
+/// How often a car passes an emulated sensor
+const EMULATED_PASS_PERIOD: Duration = Duration::from_secs(5);
+
+/// Where readings come from, parsed from strings like `tfluna:/dev/ttyUSB0`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SensorBackend {
+    /// A real sensor on the given port, or the first one found if None. Written `tfluna[:port]`.
+    TfLuna(Option<PathBuf>),
+    /// Random distances. Written `mock`.
+    Mock,
+    /// A recording, replayed at the given speed. Written `replay:file[@speed]`.
+    Replay(PathBuf, f64),
+    /// An emulated sensor with a car passing every few seconds, using the real driver. Written `pty`.
+    Pty,
+}
+
+impl FromStr for SensorBackend {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, arg) = match s.split_once(':') {
+            Some((kind, arg)) => (kind, Some(arg)),
+            None => (s, None),
+        };
+
+        Ok(match (kind, arg) {
+            ("tfluna", port) => SensorBackend::TfLuna(port.map(PathBuf::from)),
+            ("mock", None) => SensorBackend::Mock,
+            ("pty", None) => SensorBackend::Pty,
+            ("replay", Some(arg)) => match arg.rsplit_once('@') {
+                Some((file, speed)) => {
+                    let speed: f64 = speed
+                        .parse()
+                        .map_err(|_| ConfigError::BadSensorBackend(s.to_string()))?;
+                    // Infinity replays as fast as possible, but the replay never advances at 0
+                    if speed.is_nan() || speed <= 0.0 {
+                        return Err(ConfigError::BadSensorBackend(s.to_string()));
+                    }
+                    SensorBackend::Replay(file.into(), speed)
+                }
+                None => SensorBackend::Replay(arg.into(), 1.0),
+            },
+            _ => return Err(ConfigError::BadSensorBackend(s.to_string())),
+        })
+    }
+}
+
+/// Connects to the distance sensor. Model is only used by real sensors.
+///
+/// If record_file is set, all readings are recorded to it.
+pub async fn create_sensor(
+    backend: SensorBackend,
+    model: Model,
+    record_file: Option<&Path>,
+) -> Result<Box<dyn DistanceSensor + Send>, ConfigError> {
+    let sensor: Box<dyn DistanceSensor + Send> = match backend {
+        SensorBackend::TfLuna(port) => {
+            let mut sensor = match port {
+                Some(port) => TfLuna::new(port)?,
+                // Search all serial ports, so any UART adapter or SBC works
+                None => TfLuna::discover().await?,
+            };
+            sensor.set_model(model);
+            log::info!("Using a {} sensor", model);
+
+            Box::new(sensor)
+        }
+        SensorBackend::Mock => {
+            log::info!("Creating fake sensor");
+            Box::new(MockDistanceReader::new(10, 12000))
+        }
+        SensorBackend::Replay(path, speed) => {
+            log::info!("Replaying {} at {}x", path.display(), speed);
+            Box::new(Replay::open(&path, speed).map_err(|err| ConfigError::OpenErr(path, err))?)
+        }
+        SensorBackend::Pty => Box::new(EmulatedSensor::spawn()?),
+    };
+
+    Ok(match record_file {
+        Some(path) => {
+            log::info!("Recording readings to {}", path.display());
+            Box::new(
+                Recorder::create(sensor, path)
+                    .map_err(|err| ConfigError::OpenErr(path.to_path_buf(), err))?,
+            )
+        }
+        None => sensor,
+    })
+}
+
+/// The real driver connected to an emulator, which it keeps alive.
+struct EmulatedSensor {
+    emu: Emulator,
+    sensor: TfLuna,
+    last_pass: Instant,
+}
+
+impl EmulatedSensor {
+    fn spawn() -> Result<Self, tf_luna::error::Error> {
+        let emu = Emulator::spawn(Default::default())?;
+        log::info!("Emulating sensor on {}", emu.path());
+        let sensor = TfLuna::new(emu.path().into())?;
+
+        Ok(Self {
+            emu,
+            sensor,
+            last_pass: Instant::now(),
+        })
+    }
+}
+
+#[async_trait]
+impl DistanceSensor for EmulatedSensor {
+    async fn get_reading(&mut self) -> Result<DistanceReading, SensorError> {
+        if self.last_pass.elapsed() > EMULATED_PASS_PERIOD {
+            self.emu.car_pass(500, Duration::from_millis(300));
+            self.last_pass = Instant::now();
+        }
+
+        self.sensor.get_reading().await
+    }
+
+    async fn set_frame_rate(&mut self, rate: u16) -> Result<(), SensorError> {
+        DistanceSensor::set_frame_rate(&mut self.sensor, rate).await
+    }
+
+    fn checksum_failures(&self) -> u64 {
+        DistanceSensor::checksum_failures(&self.sensor)
     }
-    #[cfg(not(feature = "no_sensor"))]
-    {
-        // Attempt to connect if connected to either the UART adapter or on a Le Potato
-        let res = TfLuna::new(PathBuf::from("/dev/ttyUSB0"));
-
-        if let Ok(sensor) = res {
-            sensor
-        } else {
-            TfLuna::new(PathBuf::from("/dev/ttyAML6")).expect("No avialible tf-lunas!")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::sensor_connection::SensorBackend;
+    use std::path::PathBuf;
+
+    #[test]
+    fn backends_parse() {
+        assert_eq!(
+            "tfluna".parse::<SensorBackend>().unwrap(),
+            SensorBackend::TfLuna(None)
+        );
+        assert_eq!(
+            "tfluna:/dev/ttyUSB0".parse::<SensorBackend>().unwrap(),
+            SensorBackend::TfLuna(Some(PathBuf::from("/dev/ttyUSB0")))
+        );
+        assert_eq!(
+            "mock".parse::<SensorBackend>().unwrap(),
+            SensorBackend::Mock
+        );
+        assert_eq!(
+            "replay:run.csv@10".parse::<SensorBackend>().unwrap(),
+            SensorBackend::Replay(PathBuf::from("run.csv"), 10.0)
+        );
+        assert_eq!(
+            "replay:run.csv@inf".parse::<SensorBackend>().unwrap(),
+            SensorBackend::Replay(PathBuf::from("run.csv"), f64::INFINITY)
+        );
+        for speed in ["0", "-1", "NaN", "-inf"] {
+            assert!(format!("replay:run.csv@{}", speed)
+                .parse::<SensorBackend>()
+                .is_err());
         }
+        assert!("replay".parse::<SensorBackend>().is_err());
+        assert!("sonar".parse::<SensorBackend>().is_err());
     }
 }
@@ -0,0 +1,65 @@
+//! Encodes frames as the sensor sends them.
+
+use tf_luna::command::OutputFormat;
+use tf_luna::types::{checksum, EightByteId};
+
+/// Encodes a control frame with the given ID and payload.
+pub fn control(id: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x5A, payload.len() as u8 + 4, id];
+    frame.extend_from_slice(payload);
+    frame.push(checksum(&frame));
+    frame
+}
+
+/// Encodes a data frame in format. Distances that don't fit the format saturate.
+pub fn reading(format: OutputFormat, dist_mm: u32, amp: u16, temp: u16) -> Vec<u8> {
+    let cm = (dist_mm / 10).min(u16::MAX as u32) as u16;
+    let mm = dist_mm.min(u16::MAX as u32) as u16;
+
+    match format {
+        OutputFormat::NineByteCm | OutputFormat::NineByteMm => {
+            let dist = if format == OutputFormat::NineByteCm {
+                cm
+            } else {
+                mm
+            };
+
+            let mut frame = vec![0x59, 0x59];
+            frame.extend_from_slice(&dist.to_le_bytes());
+            frame.extend_from_slice(&amp.to_le_bytes());
+            frame.extend_from_slice(&temp.to_le_bytes());
+            frame.push(checksum(&frame));
+            frame
+        }
+        OutputFormat::EightByteId => {
+            let mut payload = cm.to_le_bytes().to_vec();
+            payload.extend_from_slice(&amp.to_le_bytes());
+            control(EightByteId::ID, &payload)
+        }
+        OutputFormat::Pixhawk => format!("{}.{:02}\r\n", cm / 100, cm % 100).into_bytes(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::encode::reading;
+    use tf_luna::command::OutputFormat;
+    use tf_luna::types::{EightByteId, NineByteCm, Pixhawk};
+
+    #[test]
+    fn readings_roundtrip() {
+        let frame = reading(OutputFormat::NineByteCm, 3000, 1000, 2248);
+        assert_eq!(
+            frame,
+            [0x59, 0x59, 0x2C, 0x01, 0xE8, 0x03, 0xC8, 0x08, 0x9A]
+        );
+        assert_eq!(NineByteCm::try_from(frame.as_slice()).unwrap().dist, 300);
+
+        let frame = reading(OutputFormat::EightByteId, 3000, 1000, 0);
+        assert_eq!(EightByteId::try_from(frame.as_slice()).unwrap().dist, 300);
+
+        let frame = reading(OutputFormat::Pixhawk, 1234, 0, 0);
+        assert_eq!(frame, b"1.23\r\n");
+        assert_eq!(Pixhawk::try_from(frame.as_slice()).unwrap().dist, 1230);
+    }
+}
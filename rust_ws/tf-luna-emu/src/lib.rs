@@ -0,0 +1,437 @@
+//! Emulates a TFLuna over a pseudo terminal, so the drivers can be used without hardware.
+//!
+//! [`Emulator::spawn`] opens a pty pair and serves the sensor protocol on it from a background
+//! thread. Drivers connect to [`Emulator::path`] like any other serial port.
+
+mod encode;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serialport::{ClearBuffer, SerialPort, TTYPort};
+use std::io::{ErrorKind, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tf_luna::command::{Command, OutputFormat, Version};
+use tf_luna::decoder::{Frame, FrameDecoder};
+
+/// How often to check for commands while output is disabled
+const IDLE_POLL: Duration = Duration::from_millis(20);
+/// Bytes allowed to pile up unread before they are thrown away, like a UART with nobody listening
+const MAX_BACKLOG: u32 = 1024;
+
+/// A vehicle passing under the sensor.
+#[derive(Copy, Clone, Debug)]
+pub struct CarPass {
+    /// When the car arrives, relative to the emulator starting
+    pub start: Duration,
+    /// How long the car is under the sensor
+    pub duration: Duration,
+    /// Distance to the car, mm
+    pub dist: u32,
+}
+
+/// Errors injected into data frames, as the chance of each frame being affected.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Faults {
+    /// Chance of a frame having a bad checksum
+    pub checksum: f64,
+    /// Chance of a byte being dropped from a frame
+    pub drop: f64,
+}
+
+/// How the emulated sensor and its surroundings behave.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Distance to the ground when nothing is passing, mm
+    pub zero: u32,
+    /// Random noise added to each distance, +- mm
+    pub noise: u32,
+    /// Signal strength of readings
+    pub amp: u16,
+    /// Raw chip temperature of readings
+    pub temp: u16,
+    /// Firmware version to report
+    pub version: Version,
+    pub faults: Faults,
+    /// Scripted car passes
+    pub passes: Vec<CarPass>,
+    /// Seed for noise and faults, so runs can be repeated
+    pub seed: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            zero: 1500,
+            noise: 5,
+            amp: 1000,
+            // 25C
+            temp: 2248,
+            version: Version {
+                major: 3,
+                minor: 0,
+                patch: 0,
+            },
+            faults: Faults::default(),
+            passes: Vec::new(),
+            seed: 0,
+        }
+    }
+}
+
+/// Settings that can be changed by commands.
+#[derive(Copy, Clone, Debug)]
+struct Settings {
+    frame_rate: u16,
+    format: OutputFormat,
+    enabled: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            frame_rate: 100,
+            format: OutputFormat::NineByteCm,
+            enabled: true,
+        }
+    }
+}
+
+/// Handle to an emulated sensor. The sensor stops when this is dropped.
+pub struct Emulator {
+    path: String,
+    start: Instant,
+    passes: Arc<Mutex<Vec<CarPass>>>,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Emulator {
+    /// Opens a pty and starts emulating a sensor on it, with factory default settings.
+    pub fn spawn(config: Config) -> Result<Self, serialport::Error> {
+        let (master, slave) = TTYPort::pair()?;
+        let path = slave.name().expect("pty slave should be named");
+
+        let start = Instant::now();
+        let passes = Arc::new(Mutex::new(config.passes.clone()));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let mut sensor = Sensor {
+            master,
+            slave,
+            decoder: FrameDecoder::new(OutputFormat::NineByteCm),
+            rng: StdRng::seed_from_u64(config.seed),
+            config,
+            settings: Settings::default(),
+            saved: Settings::default(),
+            start,
+            passes: passes.clone(),
+            running: running.clone(),
+        };
+
+        let thread = std::thread::spawn(move || sensor.run());
+        log::info!("Emulating TFLuna on {}", path);
+
+        Ok(Self {
+            path,
+            start,
+            passes,
+            running,
+            thread: Some(thread),
+        })
+    }
+
+    /// Path of the serial port to connect drivers to.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Sends a car under the sensor now, at dist mm away.
+    pub fn car_pass(&self, dist: u32, duration: Duration) {
+        self.passes.lock().unwrap().push(CarPass {
+            start: self.start.elapsed(),
+            duration,
+            dist,
+        });
+    }
+}
+
+impl Drop for Emulator {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Emulator thread state.
+struct Sensor {
+    master: TTYPort,
+    /// Held open so the pty stays up between drivers connecting
+    slave: TTYPort,
+    decoder: FrameDecoder,
+    rng: StdRng,
+    config: Config,
+    settings: Settings,
+    /// Settings restored on reset
+    saved: Settings,
+    start: Instant,
+    passes: Arc<Mutex<Vec<CarPass>>>,
+    running: Arc<AtomicBool>,
+}
+
+impl Sensor {
+    fn run(&mut self) {
+        let mut next_frame = Instant::now();
+        let mut buf = [0u8; 64];
+
+        while self.running.load(Ordering::Relaxed) {
+            let streaming = self.settings.enabled && self.settings.frame_rate > 0;
+
+            if streaming && Instant::now() >= next_frame {
+                self.send_reading();
+
+                let period = Duration::from_secs(1) / self.settings.frame_rate as u32;
+                // Don't burst frames to catch up after a stall
+                next_frame = (next_frame + period).max(Instant::now());
+            }
+
+            let wait = if streaming {
+                next_frame.saturating_duration_since(Instant::now())
+            } else {
+                IDLE_POLL
+            };
+            if let Err(err) = self.master.set_timeout(wait.max(Duration::from_millis(1))) {
+                log::error!("Failed to set pty timeout: {}", err);
+                return;
+            }
+
+            match self.master.read(&mut buf) {
+                Ok(read) => {
+                    self.decoder.push(&buf[..read]);
+                    while let Some(frame) = self.decoder.decode() {
+                        if let Frame::Control(req) = frame {
+                            self.handle(req.id(), req.payload());
+                        }
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::TimedOut => {}
+                Err(err) => {
+                    log::error!("Failed to read pty: {}", err);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Responds to a request from the driver.
+    fn handle(&mut self, id: u8, payload: &[u8]) {
+        let Some(cmd) = Command::decode(id, payload) else {
+            log::warn!("Ignoring unknown command with id {:#x}", id);
+            return;
+        };
+        log::debug!("Received {:?}", cmd);
+
+        match cmd {
+            Command::GetVersion => {
+                let version = self.config.version;
+                self.send(&encode::control(
+                    id,
+                    &[version.patch, version.minor, version.major],
+                ));
+                return;
+            }
+            Command::SystemReset => self.settings = self.saved,
+            Command::RestoreFactoryDefaults => {
+                self.settings = Settings::default();
+                self.saved = Settings::default();
+            }
+            Command::SaveSettings => self.saved = self.settings,
+            Command::SetFrameRate(rate) => self.settings.frame_rate = rate,
+            Command::SetOutputFormat(format) => self.settings.format = format,
+            // A pty has no baud rate, so this only needs acknowledging
+            Command::SetBaudRate(_) => {}
+            Command::SetOutputEnabled(enabled) => self.settings.enabled = enabled,
+        }
+
+        match cmd {
+            Command::SystemReset | Command::RestoreFactoryDefaults | Command::SaveSettings => {
+                self.send(&encode::control(id, &[0]))
+            }
+            // Everything else echos the request
+            _ => self.send(&encode::control(id, payload)),
+        }
+    }
+
+    /// Sends a data frame of the current scene, applying faults.
+    fn send_reading(&mut self) {
+        let mut frame = encode::reading(
+            self.settings.format,
+            self.dist(),
+            self.config.amp,
+            self.config.temp,
+        );
+
+        let faults = self.config.faults;
+        if self.rng.gen_bool(faults.checksum) {
+            let last = frame.len() - 1;
+            frame[last] = frame[last].wrapping_add(1);
+        }
+        if self.rng.gen_bool(faults.drop) {
+            frame.remove(self.rng.gen_range(0..frame.len()));
+        }
+
+        self.send(&frame);
+    }
+
+    /// Distance the sensor currently sees, mm.
+    fn dist(&mut self) -> u32 {
+        let now = self.start.elapsed();
+        let mut passes = self.passes.lock().unwrap();
+        // Passes are added forever with --pass-every, so drop those that are over
+        passes.retain(|pass| now < pass.start + pass.duration);
+        let base = passes
+            .iter()
+            .find(|pass| pass.start <= now)
+            .map(|pass| pass.dist)
+            .unwrap_or(self.config.zero);
+
+        let noise = self.config.noise as i64;
+        (base as i64 + self.rng.gen_range(-noise..=noise)).max(0) as u32
+    }
+
+    /// Writes bytes to the driver.
+    fn send(&mut self, bytes: &[u8]) {
+        // With no driver reading, drop old bytes rather than letting them go stale in the pty
+        if self.slave.bytes_to_read().unwrap_or(0) > MAX_BACKLOG {
+            let _ = self.slave.clear(ClearBuffer::Input);
+        }
+
+        if let Err(err) = self.master.write_all(bytes) {
+            log::warn!("Failed to write to pty: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Config, Emulator, Faults};
+    use futures::StreamExt;
+    use std::pin::pin;
+    use std::time::{Duration, Instant};
+    use tf_luna::command::{OutputFormat, DEFAULT_BAUD_RATE};
+    use tf_luna::TfLuna;
+
+    #[test]
+    fn blocking_driver_works() {
+        let emu = Emulator::spawn(Config::default()).unwrap();
+        let mut sensor = tf_luna::blocking::TfLuna::open(
+            emu.path(),
+            DEFAULT_BAUD_RATE,
+            OutputFormat::NineByteCm,
+        )
+        .unwrap();
+
+        let dist = sensor.read().unwrap().dist_mm();
+        assert!((1490..=1510).contains(&dist));
+
+        assert_eq!(sensor.get_version().unwrap().to_string(), "3.0.0");
+
+        sensor.set_frame_rate(250).unwrap();
+        sensor.set_output_format(OutputFormat::NineByteMm).unwrap();
+        let dist = sensor.read().unwrap().dist_mm();
+        assert!((1495..=1505).contains(&dist));
+    }
+
+    #[test]
+    fn blocking_driver_stamps_readings_in_order() {
+        let emu = Emulator::spawn(Config::default()).unwrap();
+        let mut sensor = tf_luna::blocking::TfLuna::open(
+            emu.path(),
+            DEFAULT_BAUD_RATE,
+            OutputFormat::NineByteCm,
+        )
+        .unwrap();
+
+        let mut last = sensor.read_timed().unwrap();
+
+        // Let frames back up, so several are received in one read
+        std::thread::sleep(Duration::from_millis(100));
+        let resumed = Instant::now();
+
+        let mut backed_off = false;
+        for _ in 0..20 {
+            let timed = sensor.read_timed().unwrap();
+            assert!((1490..=1510).contains(&timed.reading.dist_mm()));
+            assert!(timed.instant >= last.instant);
+            assert!(timed.time >= last.time);
+
+            backed_off |= timed.instant < resumed;
+            last = timed;
+        }
+
+        assert!(backed_off);
+        assert!(last.instant > resumed);
+    }
+
+    #[tokio::test]
+    async fn async_driver_sees_car_pass() {
+        let emu = Emulator::spawn(Config {
+            faults: Faults {
+                checksum: 0.1,
+                drop: 0.1,
+            },
+            ..Default::default()
+        })
+        .unwrap();
+        let mut sensor = TfLuna::new(emu.path().into()).unwrap();
+
+        for _ in 0..100 {
+            sensor.read().await.unwrap();
+        }
+        assert!(sensor.stats().bad_frames > 0);
+
+        emu.car_pass(500, Duration::from_millis(200));
+
+        let mut saw_car = false;
+        for _ in 0..50 {
+            if sensor.read().await.unwrap().dist_mm() < 1000 {
+                saw_car = true;
+                break;
+            }
+        }
+
+        assert!(saw_car);
+    }
+
+    #[tokio::test]
+    async fn stream_stamps_readings_in_order() {
+        let emu = Emulator::spawn(Config::default()).unwrap();
+        let sensor = TfLuna::new(emu.path().into()).unwrap();
+        let mut stream = pin!(sensor.into_stream());
+
+        let mut last = stream.next().await.unwrap().unwrap();
+
+        // Let frames back up, so several are received in one read
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let resumed = Instant::now();
+
+        let mut backed_off = false;
+        for _ in 0..20 {
+            let timed = stream.next().await.unwrap().unwrap();
+            assert!((1490..=1510).contains(&timed.reading.dist_mm()));
+            assert!(timed.instant >= last.instant);
+            assert!(timed.time >= last.time);
+
+            // Only frames waiting behind others can be stamped before they were read
+            backed_off |= timed.instant < resumed;
+            last = timed;
+        }
+
+        assert!(backed_off);
+        assert!(last.instant > resumed);
+    }
+}
@@ -0,0 +1,81 @@
+use clap::Parser;
+use std::path::PathBuf;
+use std::time::Duration;
+use tf_luna_emu::{Config, Emulator, Faults};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Symlink to create to the emulated port, so it has a stable path.
+    #[arg(short, long)]
+    link: Option<PathBuf>,
+    /// Distance to the ground in mm.
+    #[arg(short, long, default_value_t = 1500)]
+    zero: u32,
+    /// Random noise added to readings, +- mm.
+    #[arg(long, default_value_t = 5)]
+    noise: u32,
+    /// Signal strength of readings.
+    #[arg(long, default_value_t = 1000)]
+    amp: u16,
+    /// Chance of each frame having a bad checksum.
+    #[arg(long, default_value_t = 0.0, value_parser = parse_chance)]
+    checksum_errors: f64,
+    /// Chance of each frame having a byte dropped.
+    #[arg(long, default_value_t = 0.0, value_parser = parse_chance)]
+    drops: f64,
+    /// Seconds between car passes. No cars pass if unset.
+    #[arg(short, long)]
+    pass_every: Option<f64>,
+    /// Distance to passing cars in mm.
+    #[arg(long, default_value_t = 500)]
+    pass_dist: u32,
+    /// How long each car is under the sensor in ms.
+    #[arg(long, default_value_t = 300)]
+    pass_ms: u64,
+}
+
+/// Parses a chance, which must be between 0 and 1.
+fn parse_chance(arg: &str) -> Result<f64, String> {
+    let chance: f64 = arg.parse().map_err(|err| format!("{}", err))?;
+    if !(0.0..=1.0).contains(&chance) {
+        return Err(format!("{} is not between 0 and 1", chance));
+    }
+    Ok(chance)
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let emu = Emulator::spawn(Config {
+        zero: args.zero,
+        noise: args.noise,
+        amp: args.amp,
+        faults: Faults {
+            checksum: args.checksum_errors,
+            drop: args.drops,
+        },
+        ..Default::default()
+    })
+    .expect("Failed to open pty");
+
+    println!("Emulating TFLuna on {}", emu.path());
+
+    if let Some(link) = &args.link {
+        let _ = std::fs::remove_file(link);
+        std::os::unix::fs::symlink(emu.path(), link).expect("Failed to create link");
+        println!("Linked to {}", link.display());
+    }
+
+    let pass = Duration::from_millis(args.pass_ms);
+    loop {
+        match args.pass_every {
+            Some(secs) => {
+                std::thread::sleep(Duration::from_secs_f64(secs));
+                println!("Car passing");
+                emu.car_pass(args.pass_dist, pass);
+            }
+            None => std::thread::park(),
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! Blocking driver, for use without an async runtime.
+
+use crate::command::{Command, OutputFormat, Version};
+use crate::decoder::{ControlFrame, DecoderStats, Frame};
+use crate::driver::DriverState;
+use crate::error::Error;
+use crate::model::Model;
+use crate::types::{Reading, TimedReading};
+use serialport::{DataBits, Parity, SerialPort, StopBits};
+use std::io::{ErrorKind, Read, Write};
+use std::time::{Duration, Instant};
+
+/// How long to wait for the sensor to respond to a command
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(1);
+/// Read timeout of ports opened by [`TfLuna::open`]
+const PORT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Blocking TFLuna driver over any byte stream, such as a serial port.
+pub struct TfLuna<P> {
+    port: P,
+    state: DriverState,
+}
+
+impl TfLuna<Box<dyn SerialPort>> {
+    /// Opens a serial port with 8N1 at the given baud rate, expecting data frames in format.
+    pub fn open(port: &str, baud: u32, format: OutputFormat) -> Result<Self, Error> {
+        let port = serialport::new(port, baud)
+            .data_bits(DataBits::Eight)
+            .stop_bits(StopBits::One)
+            .parity(Parity::None)
+            .timeout(PORT_TIMEOUT)
+            .open()?;
+
+        Ok(Self::new(port, baud, format))
+    }
+
+    /// Changes the sensors baud rate, then switches the port over to match.
+    ///
+    /// The new rate is lost on power cycle unless [`TfLuna::save_settings`] is called afterwards.
+    pub fn set_baud_rate(&mut self, baud: u32) -> Result<(), Error> {
+        DriverState::check_baud(baud)?;
+
+        // Sensor responds at the old rate
+        self.command(Command::SetBaudRate(baud))?;
+
+        self.port.set_baud_rate(baud)?;
+        self.state.set_baud(baud);
+
+        Ok(())
+    }
+}
+
+impl<P: Read + Write> TfLuna<P> {
+    /// Wraps a port that is already configured for the sensor.
+    ///
+    /// Baud is only used to correct reading stamps, it does not configure the port.
+    pub fn new(port: P, baud: u32, format: OutputFormat) -> Self {
+        Self {
+            port,
+            state: DriverState::new(baud, format),
+        }
+    }
+
+    /// Returns the underlying port.
+    pub fn into_inner(self) -> P {
+        self.port
+    }
+
+    /// Baud rate of the port.
+    pub fn baud(&self) -> u32 {
+        self.state.baud()
+    }
+
+    /// Model of sensor connected.
+    pub fn model(&self) -> Model {
+        self.state.model()
+    }
+
+    /// Sets the model of sensor connected, which is assumed to be a TFLuna until set.
+    ///
+    /// This sets the limits for commands and reading validity, the protocol is the same.
+    pub fn set_model(&mut self, model: Model) {
+        self.state.set_model(model);
+    }
+
+    /// Format data frames are expected to be in.
+    pub fn format(&self) -> OutputFormat {
+        self.state.format()
+    }
+
+    /// Counters of frames decoded, sync losses, and bad frames since connecting.
+    pub fn stats(&self) -> DecoderStats {
+        self.state.stats()
+    }
+
+    /// Reads the next reading from the sensor, in the format it is configured to output.
+    ///
+    /// Bad frames are skipped over, so this only errors if the port does, including read timeouts.
+    pub fn read(&mut self) -> Result<Reading, Error> {
+        Ok(self.read_timed()?.reading)
+    }
+
+    /// Reads the next reading, along with the time its last byte was received.
+    pub fn read_timed(&mut self) -> Result<TimedReading, Error> {
+        loop {
+            let frame = self.next_frame()?;
+            if let Some(reading) = self.state.timed(frame) {
+                return Ok(reading);
+            }
+        }
+    }
+
+    /// Reads from the port until the decoder produces a frame.
+    fn next_frame(&mut self) -> Result<Frame, Error> {
+        loop {
+            if let Some(frame) = self.state.decode() {
+                return Ok(frame);
+            }
+
+            let mut buf = [0u8; 64];
+            let len = self.state.read_len(buf.len());
+            let read = self.port.read(&mut buf[..len])?;
+            if read == 0 {
+                return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into());
+            }
+            self.state.push(&buf[..read]);
+        }
+    }
+
+    /// Reads the firmware version of the sensor.
+    pub fn get_version(&mut self) -> Result<Version, Error> {
+        let resp = self.command(Command::GetVersion)?;
+        Ok(Version::try_from(resp.payload())?)
+    }
+
+    /// Sets the rate data frames are output at, in Hz. Must be between 1Hz and the models max.
+    pub fn set_frame_rate(&mut self, rate: u16) -> Result<(), Error> {
+        self.state.check_frame_rate(rate)?;
+
+        self.command(Command::SetFrameRate(rate))?;
+        Ok(())
+    }
+
+    /// Changes the format of data frames. Following reads will expect the new format.
+    pub fn set_output_format(&mut self, format: OutputFormat) -> Result<(), Error> {
+        self.state.check_format(format)?;
+
+        self.command(Command::SetOutputFormat(format))?;
+        self.state.set_format(format);
+
+        Ok(())
+    }
+
+    /// Starts or stops the sensor from outputting data frames.
+    pub fn set_output_enabled(&mut self, enabled: bool) -> Result<(), Error> {
+        self.command(Command::SetOutputEnabled(enabled))?;
+        Ok(())
+    }
+
+    /// Persists the current settings to the sensors flash.
+    pub fn save_settings(&mut self) -> Result<(), Error> {
+        self.command(Command::SaveSettings)?;
+        Ok(())
+    }
+
+    /// Soft resets the sensor.
+    pub fn system_reset(&mut self) -> Result<(), Error> {
+        self.command(Command::SystemReset)?;
+        Ok(())
+    }
+
+    /// Restores the sensors factory settings.
+    pub fn restore_factory_defaults(&mut self) -> Result<(), Error> {
+        self.command(Command::RestoreFactoryDefaults)?;
+        Ok(())
+    }
+
+    /// Sends a command to the sensor, returning its checked response.
+    fn command(&mut self, cmd: Command) -> Result<ControlFrame, Error> {
+        let (frame, len) = cmd.encode();
+        self.port.write_all(&frame[..len])?;
+        self.port.flush()?;
+
+        let deadline = Instant::now() + COMMAND_TIMEOUT;
+        let resp = loop {
+            if Instant::now() > deadline {
+                return Err(Error::Timeout);
+            }
+
+            match self.next_frame() {
+                Ok(frame) => {
+                    if let Some(resp) = DriverState::response(frame, &cmd) {
+                        break resp;
+                    }
+                }
+                // Port timeouts just give us a chance to check the deadline
+                Err(Error::IoErr(err))
+                    if matches!(err.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {}
+                Err(err) => return Err(err),
+            }
+        };
+
+        cmd.check_response(resp.payload())?;
+        Ok(resp)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::blocking::TfLuna;
+    use crate::command::OutputFormat;
+    use std::io::{Cursor, Read, Write};
+
+    /// Port that reads from a script and discards writes
+    struct ScriptedPort(Cursor<Vec<u8>>);
+
+    impl Read for ScriptedPort {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl Write for ScriptedPort {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn reads_and_commands() {
+        let mut script = vec![0x59, 0x59, 0x2C, 0x01, 0xE8, 0x03, 0x00, 0x09, 0xD3];
+        script.extend([0x5A, 0x07, 0x01, 0x03, 0x02, 0x01, 0x68]);
+
+        let mut sensor = TfLuna::new(
+            ScriptedPort(Cursor::new(script)),
+            115200,
+            OutputFormat::NineByteCm,
+        );
+
+        assert_eq!(sensor.read().unwrap().dist_mm(), 3000);
+
+        let version = sensor.get_version().unwrap();
+        assert_eq!((version.major, version.minor, version.patch), (1, 2, 3));
+
+        // Script has run out
+        assert!(sensor.read().is_err());
+    }
+}
@@ -0,0 +1,253 @@
+//! Control frame commands that configure the sensor.
+
+use crate::error::FrameError;
+use crate::types::checksum;
+
+/// Longest request frame any command encodes to.
+pub const MAX_COMMAND_LEN: usize = 8;
+/// Factory default baud rate
+pub const DEFAULT_BAUD_RATE: u32 = 115200;
+/// Baud rates the sensor can be configured to, most likely first
+pub const BAUD_RATES: [u32; 9] = [
+    DEFAULT_BAUD_RATE,
+    9600,
+    19200,
+    38400,
+    57600,
+    230400,
+    256000,
+    460800,
+    921600,
+];
+
+/// Data output formats the sensor can be switched between.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[repr(u8)]
+pub enum OutputFormat {
+    /// Standard 9 byte frame, distance in cm. This is the factory default.
+    NineByteCm = 0x01,
+    /// Pixhawk ASCII string, distance in m
+    Pixhawk = 0x02,
+    /// Standard 9 byte frame, distance in mm
+    NineByteMm = 0x06,
+    /// 8 byte frame with ID 0x20, distance in cm
+    EightByteId = 0x08,
+}
+
+impl TryFrom<u8> for OutputFormat {
+    type Error = u8;
+
+    /// Converts a format code as sent by the sensor, returning the code if unknown.
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(Self::NineByteCm),
+            0x02 => Ok(Self::Pixhawk),
+            0x06 => Ok(Self::NineByteMm),
+            0x08 => Ok(Self::EightByteId),
+            other => Err(other),
+        }
+    }
+}
+
+/// Requests that can be sent to the sensor.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Command {
+    /// Reads the firmware version
+    GetVersion,
+    /// Soft resets the sensor
+    SystemReset,
+    /// Sets the output rate in Hz
+    SetFrameRate(u16),
+    /// Sets the serial baud rate
+    SetBaudRate(u32),
+    /// Changes the data frame format
+    SetOutputFormat(OutputFormat),
+    /// Starts or stops the sensor from sending data frames
+    SetOutputEnabled(bool),
+    /// Resets all settings to factory defaults
+    RestoreFactoryDefaults,
+    /// Persists the current settings, otherwise they are lost on power cycle
+    SaveSettings,
+}
+
+impl Command {
+    /// Control frame ID of the command. Responses share the ID of their request.
+    pub fn id(&self) -> u8 {
+        match self {
+            Command::GetVersion => 0x01,
+            Command::SystemReset => 0x02,
+            Command::SetFrameRate(_) => 0x03,
+            Command::SetOutputFormat(_) => 0x05,
+            Command::SetBaudRate(_) => 0x06,
+            Command::SetOutputEnabled(_) => 0x07,
+            Command::RestoreFactoryDefaults => 0x10,
+            Command::SaveSettings => 0x11,
+        }
+    }
+
+    /// Decodes a request from its control frame ID and payload, as the sensor would.
+    pub fn decode(id: u8, payload: &[u8]) -> Option<Self> {
+        Some(match (id, payload) {
+            (0x01, []) => Command::GetVersion,
+            (0x02, []) => Command::SystemReset,
+            (0x03, [lo, hi]) => Command::SetFrameRate(u16::from_le_bytes([*lo, *hi])),
+            (0x05, [fmt]) => Command::SetOutputFormat(OutputFormat::try_from(*fmt).ok()?),
+            (0x06, [a, b, c, d]) => Command::SetBaudRate(u32::from_le_bytes([*a, *b, *c, *d])),
+            (0x07, [enabled]) => Command::SetOutputEnabled(*enabled != 0),
+            (0x10, []) => Command::RestoreFactoryDefaults,
+            (0x11, []) => Command::SaveSettings,
+            _ => return None,
+        })
+    }
+
+    /// Encodes the command into a control frame. Returns the buffer and how many bytes of it are used.
+    pub fn encode(&self) -> ([u8; MAX_COMMAND_LEN], usize) {
+        let mut buf = [0u8; MAX_COMMAND_LEN];
+        let mut len = 3;
+
+        match self {
+            Command::SetFrameRate(rate) => {
+                buf[3..5].copy_from_slice(&rate.to_le_bytes());
+                len += 2;
+            }
+            Command::SetBaudRate(baud) => {
+                buf[3..7].copy_from_slice(&baud.to_le_bytes());
+                len += 4;
+            }
+            Command::SetOutputFormat(fmt) => {
+                buf[3] = *fmt as u8;
+                len += 1;
+            }
+            Command::SetOutputEnabled(enabled) => {
+                buf[3] = *enabled as u8;
+                len += 1;
+            }
+            _ => {}
+        }
+
+        // Len includes the checksum
+        buf[0] = 0x5A;
+        buf[1] = len as u8 + 1;
+        buf[2] = self.id();
+        buf[len] = checksum(&buf[..len]);
+
+        (buf, len + 1)
+    }
+
+    /// Checks the payload of the response to this command, erroring if the sensor reported a
+    /// failure or did not echo the request back.
+    pub fn check_response(&self, payload: &[u8]) -> Result<(), FrameError> {
+        match self {
+            Command::GetVersion if payload.len() == 3 => Ok(()),
+            Command::SystemReset | Command::RestoreFactoryDefaults | Command::SaveSettings => {
+                match payload {
+                    [0] => Ok(()),
+                    [_] => Err(FrameError::CommandFailed),
+                    _ => Err(FrameError::UnexpectedResponse),
+                }
+            }
+            Command::SetFrameRate(_)
+            | Command::SetBaudRate(_)
+            | Command::SetOutputFormat(_)
+            | Command::SetOutputEnabled(_) => {
+                // Echoed payload is the request minus head, len, id, and checksum
+                let (req, len) = self.encode();
+                if payload == &req[3..len - 1] {
+                    Ok(())
+                } else {
+                    Err(FrameError::UnexpectedResponse)
+                }
+            }
+            _ => Err(FrameError::UnexpectedResponse),
+        }
+    }
+}
+
+/// Firmware version of the sensor
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Version {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
+}
+
+impl TryFrom<&[u8]> for Version {
+    type Error = FrameError;
+
+    /// Parses the payload of a [`Command::GetVersion`] response.
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        match value {
+            [patch, minor, major] => Ok(Self {
+                major: *major,
+                minor: *minor,
+                patch: *patch,
+            }),
+            _ => Err(FrameError::UnexpectedResponse),
+        }
+    }
+}
+
+impl core::fmt::Display for Version {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::command::{Command, OutputFormat};
+    use crate::error::FrameError;
+
+    #[test]
+    fn commands_encode() {
+        let (buf, len) = Command::GetVersion.encode();
+        assert_eq!(&buf[..len], &[0x5A, 0x04, 0x01, 0x5F]);
+
+        let (buf, len) = Command::SetFrameRate(100).encode();
+        assert_eq!(&buf[..len], &[0x5A, 0x06, 0x03, 0x64, 0x00, 0xC7]);
+
+        let (buf, len) = Command::SetOutputFormat(OutputFormat::NineByteMm).encode();
+        assert_eq!(&buf[..len], &[0x5A, 0x05, 0x05, 0x06, 0x6A]);
+
+        let (buf, len) = Command::SetBaudRate(9600).encode();
+        assert_eq!(
+            &buf[..len],
+            &[0x5A, 0x08, 0x06, 0x80, 0x25, 0x00, 0x00, 0x0D]
+        );
+
+        let (buf, len) = Command::SaveSettings.encode();
+        assert_eq!(&buf[..len], &[0x5A, 0x04, 0x11, 0x6F]);
+    }
+
+    #[test]
+    fn commands_decode() {
+        for cmd in [
+            Command::GetVersion,
+            Command::SetFrameRate(100),
+            Command::SetBaudRate(9600),
+            Command::SetOutputFormat(OutputFormat::Pixhawk),
+            Command::SetOutputEnabled(false),
+        ] {
+            let (buf, len) = cmd.encode();
+            assert_eq!(Command::decode(buf[2], &buf[3..len - 1]), Some(cmd));
+        }
+
+        assert_eq!(Command::decode(0x05, &[0x42]), None);
+    }
+
+    #[test]
+    fn responses_checked() {
+        assert!(Command::SetFrameRate(100)
+            .check_response(&[0x64, 0x00])
+            .is_ok());
+        assert_eq!(
+            Command::SetFrameRate(100).check_response(&[0x0A, 0x00]),
+            Err(FrameError::UnexpectedResponse)
+        );
+        assert!(Command::SaveSettings.check_response(&[0]).is_ok());
+        assert_eq!(
+            Command::SaveSettings.check_response(&[1]),
+            Err(FrameError::CommandFailed)
+        );
+    }
+}
@@ -0,0 +1,347 @@
+//! Streaming decoder that splits raw serial bytes into frames.
+
+use crate::command::OutputFormat;
+use crate::error::FrameError;
+use crate::types::{ControlFrameRaw, EightByteId, NineByteCm, NineByteMm, Pixhawk, Reading};
+use core::time::Duration;
+
+/// Bytes buffered by the decoder. Must fit several of the largest frame.
+const BUF_LEN: usize = 64;
+/// Length of the 9 byte data frames
+const NINE_BYTE_LEN: usize = 9;
+/// Longest control frame we will wait for. The sensor never sends anything longer, so a longer
+/// length means we synced on a 0x5A inside another frame.
+const MAX_CONTROL_LEN: usize = 16;
+/// Longest line the Pixhawk format can produce, including the line ending
+const MAX_PIXHAWK_LEN: usize = 16;
+
+/// A frame decoded off the stream
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Frame {
+    /// Data frame, in the format the decoder is configured for
+    Data(Reading),
+    /// Control frame, usually a response to a command
+    Control(ControlFrame),
+}
+
+/// Owned copy of a control frame.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct ControlFrame {
+    id: u8,
+    payload: [u8; MAX_CONTROL_LEN],
+    payload_len: usize,
+}
+
+impl ControlFrame {
+    /// Control frame type
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Data payload, empty if the frame has none
+    pub fn payload(&self) -> &[u8] {
+        &self.payload[..self.payload_len]
+    }
+}
+
+impl From<ControlFrameRaw<'_>> for ControlFrame {
+    fn from(raw: ControlFrameRaw<'_>) -> Self {
+        let mut payload = [0u8; MAX_CONTROL_LEN];
+        let payload_len = raw.payload().len();
+        payload[..payload_len].copy_from_slice(raw.payload());
+
+        Self {
+            id: raw.id(),
+            payload,
+            payload_len,
+        }
+    }
+}
+
+/// Counters describing the health of the stream.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub struct DecoderStats {
+    /// Frames successfully decoded
+    pub frames: u64,
+    /// Times bytes had to be skipped to find a frame head. A run of skipped bytes counts once.
+    pub sync_losses: u64,
+    /// Frames with a valid head that failed their checksum or could not be parsed
+    pub bad_frames: u64,
+}
+
+/// Decodes frames out of a byte stream, like a codec.
+///
+/// Bytes are fed in with [`FrameDecoder::push`], and frames taken out with [`FrameDecoder::decode`].
+/// Data frames require both head bytes, and any frame that fails to parse only drops its first
+/// byte, so a real frame starting inside it is not lost.
+pub struct FrameDecoder {
+    format: OutputFormat,
+    buf: [u8; BUF_LEN],
+    len: usize,
+    stats: DecoderStats,
+    /// If we are currently skipping bytes looking for a head
+    syncing: bool,
+    /// If the buffer starts a line, since Pixhawk heads are just digits
+    line_start: bool,
+}
+
+impl FrameDecoder {
+    /// Creates a decoder expecting data frames in format.
+    pub fn new(format: OutputFormat) -> Self {
+        Self {
+            format,
+            buf: [0u8; BUF_LEN],
+            len: 0,
+            stats: DecoderStats::default(),
+            syncing: false,
+            line_start: false,
+        }
+    }
+
+    /// Data frame format the decoder expects
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+
+    /// Changes the expected data frame format.
+    pub fn set_format(&mut self, format: OutputFormat) {
+        self.format = format;
+    }
+
+    /// Stream health counters since creation
+    pub fn stats(&self) -> DecoderStats {
+        self.stats
+    }
+
+    /// Space left in the buffer. [`FrameDecoder::decode`] frees space as frames are taken out.
+    pub fn remaining(&self) -> usize {
+        BUF_LEN - self.len
+    }
+
+    /// Bytes currently buffered. After a decode, these are the bytes received after the frame.
+    pub fn buffered(&self) -> usize {
+        self.len
+    }
+
+    /// Time the buffered bytes took on the wire at baud, with 8N1 framing.
+    pub fn buffered_time(&self, baud: u32) -> Duration {
+        Duration::from_nanos(self.len as u64 * 10 * 1_000_000_000 / baud as u64)
+    }
+
+    /// Drops all buffered bytes.
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.line_start = false;
+    }
+
+    /// Appends bytes to the buffer, returning how many fit.
+    pub fn push(&mut self, bytes: &[u8]) -> usize {
+        let count = bytes.len().min(self.remaining());
+        self.buf[self.len..self.len + count].copy_from_slice(&bytes[..count]);
+        self.len += count;
+        count
+    }
+
+    /// Decodes the next frame, or returns None if more bytes are needed.
+    pub fn decode(&mut self) -> Option<Frame> {
+        loop {
+            let head = *self.buf[..self.len].first()?;
+
+            let frame_len = match (head, self.format) {
+                (0x59, OutputFormat::NineByteCm | OutputFormat::NineByteMm) => {
+                    if self.len < 2 {
+                        return None;
+                    }
+                    if self.buf[1] != 0x59 {
+                        self.skip();
+                        continue;
+                    }
+                    NINE_BYTE_LEN
+                }
+                (0x5A, _) => {
+                    if self.len < 2 {
+                        return None;
+                    }
+                    let len = self.buf[1] as usize;
+                    if !(4..=MAX_CONTROL_LEN).contains(&len) {
+                        self.skip();
+                        continue;
+                    }
+                    len
+                }
+                // Any digit mid line would look like a head, so only sync after a line ends
+                (b'0'..=b'9', OutputFormat::Pixhawk) if self.line_start => {
+                    match self.buf[..self.len.min(MAX_PIXHAWK_LEN)]
+                        .iter()
+                        .position(|b| *b == b'\n')
+                    {
+                        Some(idx) => idx + 1,
+                        None if self.len < MAX_PIXHAWK_LEN => return None,
+                        None => {
+                            self.skip();
+                            continue;
+                        }
+                    }
+                }
+                _ => {
+                    self.skip();
+                    continue;
+                }
+            };
+
+            if self.len < frame_len {
+                return None;
+            }
+
+            match self.parse(&self.buf[..frame_len]) {
+                Ok(frame) => {
+                    self.consume(frame_len);
+                    self.syncing = false;
+                    self.line_start = true;
+                    self.stats.frames += 1;
+                    return Some(frame);
+                }
+                Err(err) => {
+                    log::trace!("Dropping bad frame: {}", err);
+                    self.stats.bad_frames += 1;
+                    // Slide forward a byte, since a real frame may start within the bad one
+                    self.consume(1);
+                    self.line_start = false;
+                }
+            }
+        }
+    }
+
+    /// Parses a complete frame.
+    fn parse(&self, frame: &[u8]) -> Result<Frame, FrameError> {
+        Ok(match (frame[0], self.format) {
+            (0x59, OutputFormat::NineByteCm) => {
+                Frame::Data(Reading::NineByteCm(NineByteCm::try_from(frame)?))
+            }
+            (0x59, _) => Frame::Data(Reading::NineByteMm(NineByteMm::try_from(frame)?)),
+            (0x5A, OutputFormat::EightByteId) if frame[2] == EightByteId::ID => {
+                Frame::Data(Reading::EightByteId(EightByteId::try_from(frame)?))
+            }
+            (0x5A, _) => Frame::Control(ControlFrameRaw::try_from(frame)?.into()),
+            _ => Frame::Data(Reading::Pixhawk(Pixhawk::try_from(frame)?)),
+        })
+    }
+
+    /// Drops a byte that does not start a frame.
+    fn skip(&mut self) {
+        if !self.syncing {
+            self.syncing = true;
+            self.stats.sync_losses += 1;
+        }
+        self.line_start = self.buf[0] == b'\n';
+        self.consume(1);
+    }
+
+    /// Removes count bytes from the front of the buffer.
+    fn consume(&mut self, count: usize) {
+        self.buf.copy_within(count..self.len, 0);
+        self.len -= count;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::command::OutputFormat;
+    use crate::decoder::{Frame, FrameDecoder};
+    use crate::types::Reading;
+
+    /// Frame with dist 300cm, amp 1000
+    const FRAME: [u8; 9] = [0x59, 0x59, 0x2C, 0x01, 0xE8, 0x03, 0x00, 0x09, 0xD3];
+
+    fn dist(frame: Option<Frame>) -> u32 {
+        match frame {
+            Some(Frame::Data(reading)) => reading.dist_mm(),
+            other => panic!("Expected data frame, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decodes_across_pushes() {
+        let mut dec = FrameDecoder::new(OutputFormat::NineByteCm);
+
+        dec.push(&FRAME[..4]);
+        assert!(dec.decode().is_none());
+        dec.push(&FRAME[4..]);
+        assert_eq!(dist(dec.decode()), 3000);
+        assert!(dec.decode().is_none());
+        assert_eq!(dec.stats().frames, 1);
+    }
+
+    #[test]
+    fn resyncs_on_double_head() {
+        let mut dec = FrameDecoder::new(OutputFormat::NineByteCm);
+
+        // Tail end of a frame containing 0x59, followed by two good frames
+        dec.push(&[0x59, 0x20, 0x59, 0x03, 0x11]);
+        dec.push(&FRAME);
+        dec.push(&FRAME);
+
+        assert_eq!(dist(dec.decode()), 3000);
+        assert_eq!(dist(dec.decode()), 3000);
+        assert!(dec.decode().is_none());
+        assert_eq!(dec.stats().sync_losses, 1);
+        assert_eq!(dec.stats().bad_frames, 0);
+    }
+
+    #[test]
+    fn bad_checksum_slides_one_byte() {
+        let mut dec = FrameDecoder::new(OutputFormat::NineByteCm);
+
+        // A frame cut short by a dropped byte, with the next frame immediately after
+        dec.push(&FRAME[..6]);
+        dec.push(&FRAME);
+
+        assert_eq!(dist(dec.decode()), 3000);
+        assert!(dec.decode().is_none());
+        assert_eq!(dec.stats().bad_frames, 1);
+    }
+
+    #[test]
+    fn separates_control_frames() {
+        let mut dec = FrameDecoder::new(OutputFormat::NineByteMm);
+
+        dec.push(&FRAME);
+        dec.push(&[0x5A, 0x07, 0x01, 0x03, 0x02, 0x01, 0x68]);
+        dec.push(&FRAME);
+
+        assert_eq!(dist(dec.decode()), 300);
+        match dec.decode() {
+            Some(Frame::Control(ctrl)) => {
+                assert_eq!(ctrl.id(), 0x01);
+                assert_eq!(ctrl.payload(), &[0x03, 0x02, 0x01]);
+            }
+            other => panic!("Expected control frame, got {:?}", other),
+        }
+        assert_eq!(dist(dec.decode()), 300);
+    }
+
+    #[test]
+    fn decodes_other_formats() {
+        let mut dec = FrameDecoder::new(OutputFormat::Pixhawk);
+        dec.push(b"\n1.23\r\n0.45\r\n");
+        assert_eq!(dist(dec.decode()), 1230);
+        assert_eq!(dist(dec.decode()), 450);
+
+        // Started mid line, so the rest of the line is not a reading
+        let mut dec = FrameDecoder::new(OutputFormat::Pixhawk);
+        dec.push(b"23\r\n0.45\r\n");
+        assert_eq!(dist(dec.decode()), 450);
+        assert!(dec.decode().is_none());
+
+        // Same after a bad line
+        dec.push(b"1.2x3\r\n7.5\r\n");
+        assert_eq!(dist(dec.decode()), 7500);
+
+        let mut dec = FrameDecoder::new(OutputFormat::EightByteId);
+        dec.push(&[0x5A, 0x08, 0x20, 0x2C, 0x01, 0xE8, 0x03, 0x9A]);
+        assert!(matches!(
+            dec.decode(),
+            Some(Frame::Data(Reading::EightByteId(_)))
+        ));
+    }
+}
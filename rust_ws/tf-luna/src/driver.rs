@@ -0,0 +1,147 @@
+//! State and checks shared by the blocking and async drivers, which only differ in how they do IO.
+
+use crate::command::{Command, OutputFormat, BAUD_RATES};
+use crate::decoder::{ControlFrame, DecoderStats, Frame, FrameDecoder};
+use crate::error::Error;
+use crate::model::Model;
+use crate::types::TimedReading;
+use std::time::{Instant, SystemTime};
+
+/// Everything a driver tracks besides its port.
+pub(crate) struct DriverState {
+    /// Baud rate of the port, used to stamp readings
+    baud: u32,
+    decoder: FrameDecoder,
+    model: Model,
+    /// Monotonic and wall time of the last read from the port
+    last_read: (Instant, SystemTime),
+    /// Stamps of the last reading, which following readings can't be stamped before
+    last_stamp: (Instant, SystemTime),
+}
+
+impl DriverState {
+    pub fn new(baud: u32, format: OutputFormat) -> Self {
+        Self {
+            baud,
+            decoder: FrameDecoder::new(format),
+            model: Model::default(),
+            last_read: (Instant::now(), SystemTime::now()),
+            last_stamp: (Instant::now(), SystemTime::now()),
+        }
+    }
+
+    pub fn baud(&self) -> u32 {
+        self.baud
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    pub fn set_model(&mut self, model: Model) {
+        self.model = model;
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        self.decoder.format()
+    }
+
+    pub fn stats(&self) -> DecoderStats {
+        self.decoder.stats()
+    }
+
+    /// Space to read into, at most len.
+    pub fn read_len(&self, len: usize) -> usize {
+        self.decoder.remaining().min(len)
+    }
+
+    /// Buffers bytes just read from the port, marking when they arrived.
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.last_read = (Instant::now(), SystemTime::now());
+        self.decoder.push(bytes);
+    }
+
+    pub fn decode(&mut self) -> Option<Frame> {
+        self.decoder.decode()
+    }
+
+    /// Turns a frame into a stamped reading, or None if it was a control frame.
+    pub fn timed(&mut self, frame: Frame) -> Option<TimedReading> {
+        let reading = match frame {
+            Frame::Data(reading) => reading,
+            Frame::Control(ctrl) => {
+                log::trace!(
+                    "Ignoring unsolicited control frame with id {:#x}",
+                    ctrl.id()
+                );
+                return None;
+            }
+        };
+
+        // Bytes still buffered arrived after this frame, so back the stamp off by them
+        let after = self.decoder.buffered_time(self.baud);
+        let (instant, time) = self.last_read;
+        let instant = instant.checked_sub(after).unwrap_or(instant);
+        let time = time.checked_sub(after).unwrap_or(time);
+
+        // A backlog arrives faster than the baud rate, which would back off too far
+        let (last_instant, last_time) = self.last_stamp;
+        self.last_stamp = (instant.max(last_instant), time.max(last_time));
+
+        Some(TimedReading {
+            reading,
+            validity: reading.validity(&self.model.capabilities()),
+            instant: self.last_stamp.0,
+            time: self.last_stamp.1,
+        })
+    }
+
+    /// Returns the frame if it responds to cmd.
+    pub fn response(frame: Frame, cmd: &Command) -> Option<ControlFrame> {
+        match frame {
+            Frame::Control(resp) if resp.id() == cmd.id() => Some(resp),
+            Frame::Control(resp) => {
+                log::trace!("Ignoring control frame with id {:#x}", resp.id());
+                None
+            }
+            Frame::Data(_) => None,
+        }
+    }
+
+    /// Checks the model can output at rate.
+    pub fn check_frame_rate(&self, rate: u16) -> Result<(), Error> {
+        let max = self.model.capabilities().max_frame_rate;
+        if !(1..=max).contains(&rate) {
+            return Err(Error::FrameRateOutOfRange(rate, max));
+        }
+        Ok(())
+    }
+
+    /// Checks the model can output format.
+    pub fn check_format(&self, format: OutputFormat) -> Result<(), Error> {
+        if !self.model.capabilities().formats.contains(&format) {
+            return Err(Error::UnsupportedFormat(format));
+        }
+        Ok(())
+    }
+
+    /// Checks the sensor supports baud.
+    pub fn check_baud(baud: u32) -> Result<(), Error> {
+        if !BAUD_RATES.contains(&baud) {
+            return Err(Error::UnsupportedBaudRate(baud));
+        }
+        Ok(())
+    }
+
+    /// Expects data frames in format from now on.
+    pub fn set_format(&mut self, format: OutputFormat) {
+        self.decoder.set_format(format);
+    }
+
+    /// Switches to a new baud rate.
+    pub fn set_baud(&mut self, baud: u32) {
+        self.baud = baud;
+        // Anything buffered was received at the old rate
+        self.decoder.clear();
+    }
+}
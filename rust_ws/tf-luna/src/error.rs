@@ -1,19 +1,62 @@
-use thiserror::Error;
+#[cfg(feature = "std")]
+use crate::command::OutputFormat;
+use core::fmt::{Display, Formatter};
 
-#[derive(Error, Debug)]
-pub enum Error {
-    #[error("Head was not expected value")]
+/// Errors from parsing frames and responses. These need neither std nor allocation.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum FrameError {
     InvalidHead,
     /// Packet was too short
-    #[error("Packet was too short")]
     TooShort,
+    ChecksumFailed,
+    /// Pixhawk frame was not a valid number
+    InvalidAscii,
+    /// Response payload did not match what the command expects
+    UnexpectedResponse,
+    /// Sensor responded that the command failed
+    CommandFailed,
+}
+
+impl Display for FrameError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            FrameError::InvalidHead => "Head was not expected value",
+            FrameError::TooShort => "Packet was too short",
+            FrameError::ChecksumFailed => "Checksum failed",
+            FrameError::InvalidAscii => "Pixhawk frame was not a valid distance",
+            FrameError::UnexpectedResponse => "Unexpected command response",
+            FrameError::CommandFailed => "Sensor reported command failure",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FrameError {}
+
+/// Errors from the drivers.
+#[cfg(feature = "std")]
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Frame(#[from] FrameError),
     /// Error from underlying serial port
     #[error(transparent)]
-    SerialErr(#[from] tokio_serial::Error),
+    SerialErr(#[from] serialport::Error),
     #[error(transparent)]
     IoErr(#[from] std::io::Error),
     #[error("Tf luna port not found")]
     PortNotFound,
-    #[error("Checksum failed")]
-    ChecksumFailed
+    /// Sensor did not respond to a command in time
+    #[error("Timed out waiting for command response")]
+    Timeout,
+    /// Frame rate was outside of 1 to the models max, which is the second field
+    #[error("Frame rate {0}Hz is outside of 1-{1}Hz")]
+    FrameRateOutOfRange(u16, u16),
+    #[error("Output format {0:?} is not supported by the sensor")]
+    UnsupportedFormat(OutputFormat),
+    #[error("Baud rate {0} is not supported by the sensor")]
+    UnsupportedBaudRate(u32),
+    /// Sensor did not respond at any baud rate
+    #[error("Could not find the sensors baud rate")]
+    BaudRateNotFound,
 }
@@ -1,7 +1,24 @@
-//! TFLuna async driver for std environments.
+//! TFLuna driver. Other Benewake rangefinders with the same framing are supported too, see
+//! [`model::Model`].
+//!
+//! Frame parsing is `no_std` and allocation free, so it can be used on its own on an MCU. The `std`
+//! feature adds a blocking driver in [`blocking`], and the default `async` feature adds a tokio driver.
 
-pub mod types;
-mod sensor;
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "std")]
+pub mod blocking;
+pub mod command;
+pub mod decoder;
+#[cfg(feature = "std")]
+mod driver;
 pub mod error;
+pub mod model;
+#[cfg(feature = "async")]
+mod sensor;
+pub mod types;
 
+pub use command::{BAUD_RATES, DEFAULT_BAUD_RATE};
+pub use model::Model;
+#[cfg(feature = "async")]
 pub use sensor::TfLuna;
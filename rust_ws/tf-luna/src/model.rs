@@ -0,0 +1,113 @@
+//! Benewake rangefinders that share the TFLuna's framing and commands.
+
+use crate::command::OutputFormat;
+
+/// Supported sensor models.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum Model {
+    #[default]
+    TfLuna,
+    TfMiniPlus,
+    Tf02Pro,
+    Tf03,
+}
+
+/// What a model can do, from its datasheet.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Capabilities {
+    /// Furthest distance that can be measured, mm
+    pub max_range_mm: u32,
+    /// Highest frame rate that can be set, Hz
+    pub max_frame_rate: u16,
+    /// If data frames carry signal strength. Otherwise those bytes are reserved.
+    pub has_amp: bool,
+    /// Signal strength below which the distance is unreliable
+    pub min_amp: u16,
+    /// If data frames carry chip temperature. Otherwise those bytes are reserved.
+    pub has_temp: bool,
+    /// Data formats the model can output
+    pub formats: &'static [OutputFormat],
+}
+
+/// Formats shared by every model
+const COMMON_FORMATS: &[OutputFormat] = &[
+    OutputFormat::NineByteCm,
+    OutputFormat::Pixhawk,
+    OutputFormat::NineByteMm,
+];
+
+impl Model {
+    /// Every supported model
+    pub const ALL: [Model; 4] = [
+        Model::TfLuna,
+        Model::TfMiniPlus,
+        Model::Tf02Pro,
+        Model::Tf03,
+    ];
+
+    /// Capabilities of the model.
+    pub const fn capabilities(&self) -> Capabilities {
+        match self {
+            Model::TfLuna => Capabilities {
+                max_range_mm: 8_000,
+                max_frame_rate: 250,
+                has_amp: true,
+                min_amp: 100,
+                has_temp: true,
+                formats: &[
+                    OutputFormat::NineByteCm,
+                    OutputFormat::Pixhawk,
+                    OutputFormat::NineByteMm,
+                    OutputFormat::EightByteId,
+                ],
+            },
+            Model::TfMiniPlus => Capabilities {
+                max_range_mm: 12_000,
+                max_frame_rate: 1000,
+                has_amp: true,
+                min_amp: 100,
+                has_temp: true,
+                formats: COMMON_FORMATS,
+            },
+            Model::Tf02Pro => Capabilities {
+                max_range_mm: 40_000,
+                max_frame_rate: 1000,
+                has_amp: true,
+                min_amp: 60,
+                has_temp: true,
+                formats: COMMON_FORMATS,
+            },
+            Model::Tf03 => Capabilities {
+                max_range_mm: 180_000,
+                max_frame_rate: 10_000,
+                has_amp: false,
+                min_amp: 0,
+                has_temp: false,
+                formats: COMMON_FORMATS,
+            },
+        }
+    }
+
+    /// Short name of the model, like "tf-luna".
+    pub fn name(&self) -> &'static str {
+        match self {
+            Model::TfLuna => "tf-luna",
+            Model::TfMiniPlus => "tf-mini-plus",
+            Model::Tf02Pro => "tf02-pro",
+            Model::Tf03 => "tf03",
+        }
+    }
+
+    /// Looks up a model by its [`Model::name`], ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|model| model.name().eq_ignore_ascii_case(name))
+    }
+}
+
+impl core::fmt::Display for Model {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.name())
+    }
+}
@@ -1,39 +1,262 @@
+use crate::command::{Command, OutputFormat, Version, BAUD_RATES, DEFAULT_BAUD_RATE};
+use crate::decoder::{ControlFrame, DecoderStats, Frame};
+use crate::driver::DriverState;
 use crate::error::Error;
-use crate::types::NineByteCm;
+use crate::model::Model;
+use crate::types::{Reading, TimedReading};
+use futures::Stream;
 use std::path::PathBuf;
-use tokio::io::AsyncReadExt;
-use tokio_serial::{DataBits, Parity, SerialStream, StopBits};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_serial::{DataBits, Parity, SerialPort, SerialPortType, SerialStream, StopBits};
+
+/// How long to wait for the sensor to respond to a command
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(1);
+/// How long to wait for a port to show it is a sensor when discovering
+const PROBE_TIMEOUT: Duration = Duration::from_millis(250);
 
 pub struct TfLuna {
     port: SerialStream,
+    state: DriverState,
 }
 
 impl TfLuna {
     /// Connects to a TFLuna, if possible.
+    ///
+    /// This assumes the sensor is outputting its default format, [`OutputFormat::NineByteCm`].
     pub fn new(port: PathBuf) -> Result<Self, Error> {
+        Self::with_format(port, OutputFormat::NineByteCm)
+    }
+
+    /// Connects to a TFLuna that is already configured to output the passed format.
+    ///
+    /// This does not change the sensors format, see [`TfLuna::set_output_format`] for that.
+    pub fn with_format(port: PathBuf, format: OutputFormat) -> Result<Self, Error> {
+        Self::with_baud(port, DEFAULT_BAUD_RATE, format)
+    }
+
+    /// Connects to a TFLuna at the given baud rate, expecting data frames in format.
+    pub fn with_baud(port: PathBuf, baud: u32, format: OutputFormat) -> Result<Self, Error> {
         let port = SerialStream::open(
-            &tokio_serial::new(port.as_os_str().to_str().unwrap(), 115200)
+            &tokio_serial::new(port.as_os_str().to_str().unwrap(), baud)
                 .data_bits(DataBits::Eight)
                 .stop_bits(StopBits::One)
                 .parity(Parity::None),
         )?;
 
-        Ok(Self { port })
+        Ok(Self {
+            port,
+            state: DriverState::new(baud, format),
+        })
+    }
+
+    /// Connects to a TFLuna at an unknown baud rate, trying each rate the sensor supports until it
+    /// responds. The rate found is available from [`TfLuna::baud`].
+    pub async fn probe_baud(port: PathBuf, format: OutputFormat) -> Result<Self, Error> {
+        for baud in BAUD_RATES {
+            log::trace!("Probing {} at {} baud", port.display(), baud);
+
+            if let Ok(mut sensor) = Self::with_baud(port.clone(), baud, format) {
+                if sensor.probe().await {
+                    log::info!("Found sensor on {} at {} baud", port.display(), baud);
+                    return Ok(sensor);
+                }
+            }
+        }
+
+        Err(Error::BaudRateNotFound)
+    }
+
+    /// Searches the systems serial ports for a TFLuna, returning the first one found.
+    ///
+    /// Each port is probed at every baud rate the sensor supports, looking for valid data frames or
+    /// a response to a version request. Data frames are assumed to be in the default format.
+    pub async fn discover() -> Result<Self, Error> {
+        for info in tokio_serial::available_ports()? {
+            if info.port_type == SerialPortType::BluetoothPort {
+                continue;
+            }
+
+            let sensor =
+                Self::probe_baud(PathBuf::from(&info.port_name), OutputFormat::NineByteCm).await;
+            if sensor.is_ok() {
+                return sensor;
+            }
+        }
+
+        Err(Error::PortNotFound)
+    }
+
+    /// Checks if a sensor is on the other end of the port, at the current baud rate.
+    async fn probe(&mut self) -> bool {
+        // Two frames, since a single valid frame could be chance on a garbled stream
+        let frames = tokio::time::timeout(PROBE_TIMEOUT, async {
+            self.read().await?;
+            self.read().await
+        })
+        .await;
+
+        if let Ok(Ok(_)) = frames {
+            return true;
+        }
+
+        // Sensor may have output disabled, so see if it responds to commands instead
+        matches!(
+            tokio::time::timeout(PROBE_TIMEOUT, self.get_version()).await,
+            Ok(Ok(_))
+        )
+    }
+
+    /// Baud rate the port is open at.
+    pub fn baud(&self) -> u32 {
+        self.state.baud()
+    }
+
+    /// Model of sensor connected.
+    pub fn model(&self) -> Model {
+        self.state.model()
+    }
+
+    /// Sets the model of sensor connected, which is assumed to be a TFLuna until set.
+    ///
+    /// This sets the limits for commands and reading validity, the protocol is the same.
+    pub fn set_model(&mut self, model: Model) {
+        self.state.set_model(model);
+    }
+
+    /// Format data frames are expected to be in.
+    pub fn format(&self) -> OutputFormat {
+        self.state.format()
+    }
+
+    /// Counters of frames decoded, sync losses, and bad frames since connecting.
+    pub fn stats(&self) -> DecoderStats {
+        self.state.stats()
     }
 
-    /// Reads the next reading from the sensor.
+    /// Reads the next reading from the sensor, in the format it is configured to output.
     ///
-    /// This currently only supports reading the TFLunas default output format.
-    pub async fn read(&mut self) -> Result<NineByteCm, Error> {
-        // Keep reading until we sync with the header
-        while self.port.read_u8().await? != 0x59 {}
+    /// Bad frames are skipped over, so this only errors if the port does.
+    pub async fn read(&mut self) -> Result<Reading, Error> {
+        Ok(self.read_timed().await?.reading)
+    }
+
+    /// Reads the next reading, along with the time its last byte was received.
+    pub async fn read_timed(&mut self) -> Result<TimedReading, Error> {
+        loop {
+            let frame = self.next_frame().await?;
+            if let Some(reading) = self.state.timed(frame) {
+                return Ok(reading);
+            }
+        }
+    }
+
+    /// Converts the sensor into an infinite stream of timed readings.
+    pub fn into_stream(self) -> impl Stream<Item = Result<TimedReading, Error>> {
+        futures::stream::unfold(self, |mut sensor| async move {
+            let reading = sensor.read_timed().await;
+            Some((reading, sensor))
+        })
+    }
+
+    /// Reads from the port until the decoder produces a frame.
+    async fn next_frame(&mut self) -> Result<Frame, Error> {
+        loop {
+            if let Some(frame) = self.state.decode() {
+                return Ok(frame);
+            }
+
+            let mut buf = [0u8; 64];
+            let len = self.state.read_len(buf.len());
+            let read = self.port.read(&mut buf[..len]).await?;
+            if read == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+            self.state.push(&buf[..read]);
+        }
+    }
 
-        // Read rest of message
-        let mut reading = [0u8; 8];
-        self.port.read_exact(&mut reading).await?;
+    /// Reads the firmware version of the sensor.
+    pub async fn get_version(&mut self) -> Result<Version, Error> {
+        let resp = self.command(Command::GetVersion).await?;
+        Ok(Version::try_from(resp.payload())?)
+    }
+
+    /// Sets the rate data frames are output at, in Hz. Must be between 1Hz and the models max.
+    pub async fn set_frame_rate(&mut self, rate: u16) -> Result<(), Error> {
+        self.state.check_frame_rate(rate)?;
+
+        self.command(Command::SetFrameRate(rate)).await?;
+        Ok(())
+    }
 
-        NineByteCm::try_from(reading.as_slice())
+    /// Changes the format of data frames. Following reads will expect the new format.
+    pub async fn set_output_format(&mut self, format: OutputFormat) -> Result<(), Error> {
+        self.state.check_format(format)?;
+
+        self.command(Command::SetOutputFormat(format)).await?;
+        self.state.set_format(format);
+
+        Ok(())
     }
 
-    // In the future commands to change the sensors config could be added
+    /// Changes the sensors baud rate, then switches the port over to match.
+    ///
+    /// The new rate is lost on power cycle unless [`TfLuna::save_settings`] is called afterwards.
+    pub async fn set_baud_rate(&mut self, baud: u32) -> Result<(), Error> {
+        DriverState::check_baud(baud)?;
+
+        // Sensor responds at the old rate
+        self.command(Command::SetBaudRate(baud)).await?;
+
+        self.port.set_baud_rate(baud)?;
+        self.state.set_baud(baud);
+
+        Ok(())
+    }
+
+    /// Starts or stops the sensor from outputting data frames.
+    pub async fn set_output_enabled(&mut self, enabled: bool) -> Result<(), Error> {
+        self.command(Command::SetOutputEnabled(enabled)).await?;
+        Ok(())
+    }
+
+    /// Persists the current settings to the sensors flash.
+    pub async fn save_settings(&mut self) -> Result<(), Error> {
+        self.command(Command::SaveSettings).await?;
+        Ok(())
+    }
+
+    /// Soft resets the sensor.
+    pub async fn system_reset(&mut self) -> Result<(), Error> {
+        self.command(Command::SystemReset).await?;
+        Ok(())
+    }
+
+    /// Restores the sensors factory settings.
+    pub async fn restore_factory_defaults(&mut self) -> Result<(), Error> {
+        self.command(Command::RestoreFactoryDefaults).await?;
+        Ok(())
+    }
+
+    /// Sends a command to the sensor, returning its checked response.
+    async fn command(&mut self, cmd: Command) -> Result<ControlFrame, Error> {
+        let (frame, len) = cmd.encode();
+        self.port.write_all(&frame[..len]).await?;
+        self.port.flush().await?;
+
+        let resp = tokio::time::timeout(COMMAND_TIMEOUT, async {
+            loop {
+                let frame = self.next_frame().await?;
+                if let Some(resp) = DriverState::response(frame, &cmd) {
+                    break Ok::<_, Error>(resp);
+                }
+            }
+        })
+        .await
+        .map_err(|_| Error::Timeout)??;
+
+        cmd.check_response(resp.payload())?;
+        Ok(resp)
+    }
 }
@@ -1,8 +1,17 @@
-use crate::error::Error;
+use crate::error::FrameError;
+use crate::model::Capabilities;
+#[cfg(feature = "std")]
+use std::time::{Instant, SystemTime};
+
+/// Signal strength reported when the receiver is overexposed
+pub const SATURATED_AMP: u16 = 0xFFFF;
+/// Raw distances at or above this are error codes, -4 to -1 as an i16
+const SENTINEL_DIST: u16 = 0xFFFC;
 
 /// Raw control frame, could be request or response.
 pub struct ControlFrameRaw<'a> {
     /// Should be const 0x5A
+    #[allow(dead_code)]
     head: u8,
     /// length of bytes from head to checksum
     len: u8,
@@ -11,24 +20,25 @@ pub struct ControlFrameRaw<'a> {
     /// Optional data payload, depends on frame type
     payload: Option<&'a [u8]>,
     /// Lower 8 bytes of the sum from head to payload
+    #[allow(dead_code)]
     chksum: u8,
 }
 
 impl<'a> TryFrom<&'a [u8]> for ControlFrameRaw<'a> {
-    type Error = Error;
+    type Error = FrameError;
 
     /// Parses a raw control frame from bytes.
     fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
-        let head = *value.first().ok_or(Error::TooShort)?;
+        let head = *value.first().ok_or(FrameError::TooShort)?;
         if head != 0x5A {
-            return Err(Error::InvalidHead);
+            return Err(FrameError::InvalidHead);
         }
 
-        let len = *value.get(1).ok_or(Error::TooShort)?;
+        let len = *value.get(1).ok_or(FrameError::TooShort)?;
 
-        // We don't need to check for length after this
-        if value.len() as u8 != len {
-            return Err(Error::TooShort);
+        // Head, length, id and checksum are always present. We don't need to check for length after this
+        if len < 4 || value.len() != len as usize {
+            return Err(FrameError::TooShort);
         }
 
         let id = value[2];
@@ -42,6 +52,10 @@ impl<'a> TryFrom<&'a [u8]> for ControlFrameRaw<'a> {
 
         let chksum = value[len as usize - 1];
 
+        if checksum(&value[..len as usize - 1]) != chksum {
+            return Err(FrameError::ChecksumFailed);
+        }
+
         Ok(Self {
             head,
             len,
@@ -52,6 +66,28 @@ impl<'a> TryFrom<&'a [u8]> for ControlFrameRaw<'a> {
     }
 }
 
+impl<'a> ControlFrameRaw<'a> {
+    /// Control frame type
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Length of bytes from head to checksum
+    pub fn frame_len(&self) -> u8 {
+        self.len
+    }
+
+    /// Data payload, empty if the frame has none
+    pub fn payload(&self) -> &'a [u8] {
+        self.payload.unwrap_or(&[])
+    }
+}
+
+/// Lower 8 bits of the sum of all bytes, as used by both control and data frames.
+pub fn checksum(bytes: &[u8]) -> u8 {
+    (bytes.iter().map(|b| *b as u32).sum::<u32>() & 0xFF) as u8
+}
+
 /// Default Data frame format
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub struct NineByteCm {
@@ -59,41 +95,354 @@ pub struct NineByteCm {
     pub dist: u16,
     /// Signal strength, reliable when > 100
     pub amp: u16,
-    /// Temp in C
+    /// Raw chip temperature, see [`temp_c`]
     pub temp: u16,
 }
 
 impl TryFrom<&[u8]> for NineByteCm {
-    type Error = Error;
+    type Error = FrameError;
+
+    /// Parses a frame. You may omit the header bytes.
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let (dist, amp, temp) = parse_nine_byte(value)?;
+        Ok(Self { dist, amp, temp })
+    }
+}
+
+/// Same layout as [`NineByteCm`], but with mm resolution
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct NineByteMm {
+    /// Distance in mm
+    pub dist: u16,
+    /// Signal strength, reliable when > 100
+    pub amp: u16,
+    /// Raw chip temperature, see [`temp_c`]
+    pub temp: u16,
+}
+
+impl TryFrom<&[u8]> for NineByteMm {
+    type Error = FrameError;
 
     /// Parses a frame. You may omit the header bytes.
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        if value.len() < 7 {
-            return Err(Error::TooShort);
+        let (dist, amp, temp) = parse_nine_byte(value)?;
+        Ok(Self { dist, amp, temp })
+    }
+}
+
+/// Converts a raw temperature from a data frame to °C.
+pub fn temp_c(raw: u16) -> f32 {
+    raw as f32 / 8.0 - 256.0
+}
+
+/// Parses the dist, amp, and temp out of a 9 byte frame, which may be missing its header.
+fn parse_nine_byte(value: &[u8]) -> Result<(u16, u16, u16), FrameError> {
+    if value.len() < 7 {
+        return Err(FrameError::TooShort);
+    }
+
+    // We don't care about the header bytes, so just slice them out (also allows users to omit them)
+    let value = &value[value.len() - 7..];
+
+    let dist = u16::from_le_bytes(value[0..=1].try_into().unwrap());
+    let amp = u16::from_le_bytes(value[2..=3].try_into().unwrap());
+    let temp = u16::from_le_bytes(value[4..=5].try_into().unwrap());
+
+    let chksum = value[6];
+
+    // Calculate checksum of header to temp_h
+    let mut cumsum = 0x59u32 + 0x59;
+    for byte in value.iter().take(6) {
+        cumsum += *byte as u32;
+    }
+    // Get lower 8 bits
+    let frame_sum = (cumsum & 0xFF) as u8;
+
+    if frame_sum != chksum {
+        log::trace!(
+            "Data:{:?} computed sum:{} after and:{}",
+            value,
+            cumsum,
+            frame_sum
+        );
+        return Err(FrameError::ChecksumFailed);
+    }
+
+    Ok((dist, amp, temp))
+}
+
+/// 8 byte frame, sent with the control frame head and an ID of 0x20
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct EightByteId {
+    /// Distance in cm
+    pub dist: u16,
+    /// Signal strength, reliable when > 100
+    pub amp: u16,
+}
+
+impl EightByteId {
+    /// Control frame ID used by this format
+    pub const ID: u8 = 0x20;
+}
+
+impl TryFrom<&[u8]> for EightByteId {
+    type Error = FrameError;
+
+    /// Parses a full frame, including the head.
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let frame = ControlFrameRaw::try_from(value)?;
+        if frame.id() != Self::ID {
+            return Err(FrameError::InvalidHead);
         }
 
-        // We don't care about the header bytes, so just slice them out (also allows users to omit them)
-        let value = &value[value.len() - 7..];
+        let payload = frame.payload();
+        if payload.len() < 4 {
+            return Err(FrameError::TooShort);
+        }
 
-        let dist = u16::from_le_bytes(value[0..=1].try_into().unwrap());
-        let amp = u16::from_le_bytes(value[2..=3].try_into().unwrap());
-        let temp = u16::from_le_bytes(value[4..=5].try_into().unwrap());
+        Ok(Self {
+            dist: u16::from_le_bytes(payload[0..=1].try_into().unwrap()),
+            amp: u16::from_le_bytes(payload[2..=3].try_into().unwrap()),
+        })
+    }
+}
 
-        let chksum = value[6];
+/// Pixhawk format, which is the distance in m as an ASCII string terminated by "\r\n"
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Pixhawk {
+    /// Distance in mm. The sensor only sends cm precision
+    pub dist: u32,
+}
+
+impl TryFrom<&[u8]> for Pixhawk {
+    type Error = FrameError;
+
+    /// Parses a line like "1.23\r\n". The line ending may be omitted.
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let line = value
+            .strip_suffix(b"\r\n")
+            .or_else(|| value.strip_suffix(b"\n"))
+            .unwrap_or(value);
+
+        let (int, frac) = match line.iter().position(|b| *b == b'.') {
+            Some(idx) => (&line[..idx], &line[idx + 1..]),
+            None => (line, &[][..]),
+        };
 
-        // Calculate checksum of header to temp_h
-        let mut cumsum = 0x59u32 + 0x59;
-        for byte in value.iter().take(6) {
-            cumsum += *byte as u32;
+        if int.is_empty() || frac.len() > 3 {
+            return Err(FrameError::InvalidAscii);
         }
-        // Get lower 8 bits
-        let frame_sum = (cumsum & 0xFF) as u8;
 
-        if frame_sum != chksum {
-            log::error!("Data:{:?} computed sum:{} after and:{}", value, cumsum, frame_sum);
-            return Err(Error::ChecksumFailed);
+        // Parse as fixed point mm, padding the fraction out to 3 digits
+        let mut dist = 0u32;
+        for digit in int.iter().chain(frac).chain([b'0'; 3][frac.len()..].iter()) {
+            if !digit.is_ascii_digit() {
+                return Err(FrameError::InvalidAscii);
+            }
+            dist = dist
+                .checked_mul(10)
+                .and_then(|d| d.checked_add((digit - b'0') as u32))
+                .ok_or(FrameError::InvalidAscii)?;
         }
 
-        Ok(Self { dist, amp, temp })
+        Ok(Self { dist })
+    }
+}
+
+/// A data frame in any of the formats the sensor can output.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Reading {
+    NineByteCm(NineByteCm),
+    NineByteMm(NineByteMm),
+    EightByteId(EightByteId),
+    Pixhawk(Pixhawk),
+}
+
+impl Reading {
+    /// Distance in mm, regardless of the resolution of the format.
+    pub fn dist_mm(&self) -> u32 {
+        match self {
+            Reading::NineByteCm(r) => r.dist as u32 * 10,
+            Reading::NineByteMm(r) => r.dist as u32,
+            Reading::EightByteId(r) => r.dist as u32 * 10,
+            Reading::Pixhawk(r) => r.dist,
+        }
+    }
+
+    /// Signal strength, if the format includes it.
+    pub fn amp(&self) -> Option<u16> {
+        match self {
+            Reading::NineByteCm(r) => Some(r.amp),
+            Reading::NineByteMm(r) => Some(r.amp),
+            Reading::EightByteId(r) => Some(r.amp),
+            Reading::Pixhawk(_) => None,
+        }
+    }
+
+    /// Raw temperature, if the format includes it.
+    pub fn temp(&self) -> Option<u16> {
+        match self {
+            Reading::NineByteCm(r) => Some(r.temp),
+            Reading::NineByteMm(r) => Some(r.temp),
+            Reading::EightByteId(_) | Reading::Pixhawk(_) => None,
+        }
+    }
+
+    /// Chip temperature in °C, if the format includes it.
+    pub fn temp_c(&self) -> Option<f32> {
+        self.temp().map(temp_c)
+    }
+
+    /// Distance as sent by the sensor, in the units of the format.
+    fn raw_dist(&self) -> u32 {
+        match self {
+            Reading::NineByteCm(r) => r.dist as u32,
+            Reading::NineByteMm(r) => r.dist as u32,
+            Reading::EightByteId(r) => r.dist as u32,
+            Reading::Pixhawk(r) => r.dist,
+        }
+    }
+
+    /// Classifies whether the distance can be trusted by a sensor with caps, using the signal
+    /// strength where available.
+    pub fn validity(&self, caps: &Capabilities) -> Validity {
+        let raw = self.raw_dist();
+        // Pixhawk lines aren't limited to 16 bits, so large distances there are just out of range
+        let sentinel = match self {
+            Reading::Pixhawk(_) => raw == 0,
+            _ => raw == 0 || raw >= SENTINEL_DIST as u32,
+        };
+        if sentinel {
+            return Validity::Sentinel;
+        }
+
+        match self.amp().filter(|_| caps.has_amp) {
+            Some(SATURATED_AMP) => Validity::Saturated,
+            Some(amp) if amp < caps.min_amp => Validity::LowAmp,
+            _ if self.dist_mm() > caps.max_range_mm => Validity::OutOfRange,
+            _ => Validity::Valid,
+        }
+    }
+}
+
+/// How trustworthy the distance of a reading is.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Validity {
+    Valid,
+    /// Signal too weak, such as from dark paint or a target beyond range
+    LowAmp,
+    /// Receiver overexposed, such as from sun glare or a reflector
+    Saturated,
+    /// Distance is beyond what the sensor can measure
+    OutOfRange,
+    /// Sensor sent a placeholder distance instead of a measurement
+    Sentinel,
+}
+
+/// A reading stamped with when its last byte was received.
+#[cfg(feature = "std")]
+#[derive(Copy, Clone, Debug)]
+pub struct TimedReading {
+    pub reading: Reading,
+    /// Validity for the model of sensor that took the reading
+    pub validity: Validity,
+    /// Monotonic receive time
+    pub instant: Instant,
+    /// Wall clock receive time
+    pub time: SystemTime,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::FrameError;
+    use crate::model::Model;
+    use crate::types::{
+        temp_c, ControlFrameRaw, EightByteId, NineByteCm, NineByteMm, Pixhawk, Reading, Validity,
+    };
+
+    #[test]
+    fn nine_byte_parses() {
+        let frame = [0x59, 0x59, 0x2C, 0x01, 0xE8, 0x03, 0x00, 0x09, 0xD3];
+        let cm = NineByteCm::try_from(frame.as_slice()).unwrap();
+        assert_eq!(cm.dist, 300);
+        assert_eq!(cm.amp, 1000);
+
+        // Header can be omitted
+        let mm = NineByteMm::try_from(&frame[2..]).unwrap();
+        assert_eq!(mm.dist, 300);
+
+        let mut bad = frame;
+        bad[8] += 1;
+        assert!(NineByteCm::try_from(bad.as_slice()).is_err());
+    }
+
+    #[test]
+    fn eight_byte_parses() {
+        let frame = [0x5A, 0x08, 0x20, 0x2C, 0x01, 0xE8, 0x03, 0x9A];
+        let reading = EightByteId::try_from(frame.as_slice()).unwrap();
+        assert_eq!(reading.dist, 300);
+        assert_eq!(reading.amp, 1000);
+    }
+
+    #[test]
+    fn control_frame_length_checked() {
+        let frame = [0x5A, 0x05, 0x00, 0x01, 0x60];
+        let raw = ControlFrameRaw::try_from(frame.as_slice()).unwrap();
+        assert_eq!(raw.payload(), &[0x01]);
+
+        for short in [&[0x5A, 0x02][..], &[0x5A, 0x00], &[0x5A, 0x03, 0x5D]] {
+            assert_eq!(
+                ControlFrameRaw::try_from(short).err(),
+                Some(FrameError::TooShort)
+            );
+        }
+
+        // Lengths past 255 don't wrap around to match
+        let mut long = [0u8; 260];
+        long[..4].copy_from_slice(&[0x5A, 0x04, 0x00, 0x5E]);
+        assert_eq!(
+            ControlFrameRaw::try_from(long.as_slice()).err(),
+            Some(FrameError::TooShort)
+        );
+    }
+
+    #[test]
+    fn pixhawk_parses() {
+        assert_eq!(Pixhawk::try_from(&b"1.23\r\n"[..]).unwrap().dist, 1230);
+        assert_eq!(Pixhawk::try_from(&b"0.5"[..]).unwrap().dist, 500);
+        assert_eq!(Pixhawk::try_from(&b"8"[..]).unwrap().dist, 8000);
+        assert!(Pixhawk::try_from(&b"1.2a\r\n"[..]).is_err());
+        assert!(Pixhawk::try_from(&b"\r\n"[..]).is_err());
+    }
+
+    #[test]
+    fn validity_classified() {
+        let luna = Model::TfLuna.capabilities();
+        let reading = |dist, amp| Reading::NineByteCm(NineByteCm { dist, amp, temp: 0 });
+
+        assert_eq!(reading(300, 1000).validity(&luna), Validity::Valid);
+        assert_eq!(reading(300, 40).validity(&luna), Validity::LowAmp);
+        assert_eq!(reading(300, 0xFFFF).validity(&luna), Validity::Saturated);
+        assert_eq!(reading(900, 1000).validity(&luna), Validity::OutOfRange);
+        assert_eq!(reading(0, 1000).validity(&luna), Validity::Sentinel);
+        assert_eq!(reading(0xFFFF, 1000).validity(&luna), Validity::Sentinel);
+
+        // No amp to go on, so only the distance is checked
+        let pixhawk = |dist| Reading::Pixhawk(Pixhawk { dist });
+        assert_eq!(pixhawk(1230).validity(&luna), Validity::Valid);
+        assert_eq!(pixhawk(9000).validity(&luna), Validity::OutOfRange);
+        assert_eq!(pixhawk(65535).validity(&luna), Validity::OutOfRange);
+
+        // Longer range models, and the TF03 has no amp so reserved bytes are ignored
+        let tf02 = Model::Tf02Pro.capabilities();
+        assert_eq!(reading(900, 80).validity(&tf02), Validity::Valid);
+        let tf03 = Model::Tf03.capabilities();
+        assert_eq!(reading(9000, 0).validity(&tf03), Validity::Valid);
+    }
+
+    #[test]
+    fn temp_converts() {
+        assert_eq!(temp_c(2248), 25.0);
+        assert_eq!(temp_c(2048), 0.0);
     }
 }
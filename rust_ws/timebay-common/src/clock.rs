@@ -0,0 +1,212 @@
+//! Measuring node clocks against our own from pings, like NTP does.
+
+use crate::messages::{unix_ns, ClockPongMessage, DetectionMessage};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime};
+
+/// Pings kept per node. Only the one with the lowest delay is trusted, like NTP's clock filter.
+pub const SAMPLES_KEPT: usize = 8;
+
+/// One measurement of a node's clock.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct OffsetSample {
+    /// How far the node's clock is ahead of ours, ns.
+    pub offset_ns: i64,
+    /// Round trip time of the ping, not counting the time the node took to answer, ns.
+    pub delay_ns: i64,
+}
+
+impl OffsetSample {
+    /// Measures a node's clock from its pong, received at received by our clock.
+    pub fn from_pong(pong: &ClockPongMessage, received: SystemTime) -> Self {
+        let t1 = pong.ping_sent_ns as i64;
+        let t2 = pong.received_ns as i64;
+        let t3 = pong.sent_ns as i64;
+        let t4 = unix_ns(received) as i64;
+
+        Self {
+            offset_ns: ((t2 - t1) + (t3 - t4)) / 2,
+            delay_ns: (t4 - t1) - (t3 - t2),
+        }
+    }
+
+    /// Bound on how far the offset is off, since all of the delay could be on one way.
+    pub fn error(&self) -> Duration {
+        Duration::from_nanos(self.delay_ns.max(0) as u64 / 2)
+    }
+}
+
+/// Offsets of each node's clock from ours, measured from their pongs.
+#[derive(Debug, Default, Clone)]
+pub struct ClockModel {
+    /// Id sent in our pings
+    client_id: u32,
+    /// Latest samples of each node, oldest first
+    samples: HashMap<u16, VecDeque<OffsetSample>>,
+}
+
+impl ClockModel {
+    /// Creates a model for a client that pings with client_id.
+    pub fn new(client_id: u32) -> Self {
+        Self {
+            client_id,
+            samples: HashMap::new(),
+        }
+    }
+
+    /// Id to send in pings.
+    pub fn client_id(&self) -> u32 {
+        self.client_id
+    }
+
+    /// Records a pong, received at received by our clock.
+    ///
+    /// Returns None if the pong answered another client, or a clock was stepped mid ping.
+    pub fn record(
+        &mut self,
+        pong: &ClockPongMessage,
+        received: SystemTime,
+    ) -> Option<OffsetSample> {
+        if pong.client_id != self.client_id {
+            return None;
+        }
+
+        let sample = OffsetSample::from_pong(pong, received);
+        if sample.delay_ns < 0 {
+            return None;
+        }
+
+        let samples = self.samples.entry(pong.node_id).or_default();
+        if samples.len() == SAMPLES_KEPT {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+
+        Some(sample)
+    }
+
+    /// Best measurement of a node's clock, being the kept sample with the lowest delay. None if
+    /// the node hasn't answered a ping.
+    pub fn estimate(&self, node_id: u16) -> Option<OffsetSample> {
+        self.samples
+            .get(&node_id)?
+            .iter()
+            .min_by_key(|sample| sample.delay_ns)
+            .copied()
+    }
+
+    /// Forgets the measurements of a node, like when it disconnects.
+    pub fn forget(&mut self, node_id: u16) {
+        self.samples.remove(&node_id);
+    }
+
+    /// Moves the stamps of a detection onto our clock. Detections from nodes without an estimate
+    /// are returned as is.
+    pub fn correct(&self, mut msg: DetectionMessage) -> DetectionMessage {
+        if let Some(estimate) = self.estimate(msg.node_id) {
+            (msg.stamp_s, msg.stamp_ns) = shift(msg.stamp_s, msg.stamp_ns, -estimate.offset_ns);
+            (msg.exit_stamp_s, msg.exit_stamp_ns) =
+                shift(msg.exit_stamp_s, msg.exit_stamp_ns, -estimate.offset_ns);
+        }
+        msg
+    }
+}
+
+/// Shifts a unix stamp in seconds and nanoseconds by ns.
+fn shift(secs: u64, nanos: u32, by_ns: i64) -> (u64, u32) {
+    let total = secs as i128 * 1_000_000_000 + nanos as i128 + by_ns as i128;
+    (
+        total.div_euclid(1_000_000_000) as u64,
+        total.rem_euclid(1_000_000_000) as u32,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::clock::{ClockModel, SAMPLES_KEPT};
+    use crate::messages::{ClockPongMessage, DetectionMessage};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    const MS: u64 = 1_000_000;
+
+    /// Pong from a node ahead by offset_ms, with out_ms to reach it and back_ms to return
+    fn pong(
+        node_id: u16,
+        client_id: u32,
+        offset_ms: u64,
+        out_ms: u64,
+        back_ms: u64,
+    ) -> (ClockPongMessage, SystemTime) {
+        let sent = 1_000_000 * MS;
+        let pong = ClockPongMessage {
+            node_id,
+            client_id,
+            ping_sent_ns: sent,
+            received_ns: sent + (offset_ms + out_ms) * MS,
+            sent_ns: sent + (offset_ms + out_ms + 1) * MS,
+        };
+        let received = UNIX_EPOCH + Duration::from_nanos(sent + (out_ms + 1 + back_ms) * MS);
+        (pong, received)
+    }
+
+    #[test]
+    fn lowest_delay_trusted() {
+        let mut model = ClockModel::new(7);
+
+        let (msg, received) = pong(1, 7, 30, 10, 10);
+        let sample = model.record(&msg, received).unwrap();
+        assert_eq!(sample.offset_ns, 30 * MS as i64);
+        assert_eq!(sample.delay_ns, 20 * MS as i64);
+        assert_eq!(sample.error(), Duration::from_millis(10));
+
+        // Queued on the way out, so the offset looks larger
+        let (msg, received) = pong(1, 7, 30, 50, 10);
+        assert_eq!(
+            model.record(&msg, received).unwrap().offset_ns,
+            50 * MS as i64
+        );
+        assert_eq!(model.estimate(1).unwrap().offset_ns, 30 * MS as i64);
+
+        // Other clients' pings are ignored
+        let (msg, received) = pong(1, 8, 0, 1, 1);
+        assert!(model.record(&msg, received).is_none());
+        assert_eq!(model.estimate(1).unwrap().offset_ns, 30 * MS as i64);
+
+        // Old samples are dropped, even if better
+        for _ in 0..SAMPLES_KEPT {
+            let (msg, received) = pong(1, 7, 40, 15, 15);
+            model.record(&msg, received).unwrap();
+        }
+        assert_eq!(model.estimate(1).unwrap().offset_ns, 40 * MS as i64);
+
+        assert!(model.estimate(2).is_none());
+        model.forget(1);
+        assert!(model.estimate(1).is_none());
+    }
+
+    #[test]
+    fn detections_corrected() {
+        let mut model = ClockModel::new(7);
+        let detect = DetectionMessage::new(1, 700, 100, 10 * MS as u32).with_exit(
+            UNIX_EPOCH + Duration::from_millis(100_500),
+            600,
+            20,
+        );
+
+        // Unmeasured nodes are left alone
+        assert_eq!(model.correct(detect), detect);
+
+        let (msg, received) = pong(1, 7, 30, 5, 5);
+        model.record(&msg, received);
+        let corrected = model.correct(detect);
+        assert_eq!(
+            corrected.get_stamp(),
+            UNIX_EPOCH + Duration::from_millis(99_980)
+        );
+        assert_eq!(
+            corrected.get_exit_stamp(),
+            UNIX_EPOCH + Duration::from_millis(100_470)
+        );
+        assert_eq!(corrected.dwell(), detect.dwell());
+    }
+}
@@ -1,5 +1,7 @@
 //! Shared timebay components
 
+pub mod clock;
 pub mod messages;
+pub mod sequence;
 pub mod error;
 pub mod mqttclient;
\ No newline at end of file
@@ -2,7 +2,9 @@
 
 use crate::error::ConversionError;
 use crate::error::ConversionError::NonConvertable;
-use crate::messages::MqttMessage::{Unknown, Zero};
+use crate::messages::MqttMessage::{
+    ClockPing, ClockPong, Config, ConfigApplied, Status, Unknown, Zero,
+};
 use derive_more::{Constructor, From, IsVariant, TryInto, Unwrap};
 use paho_mqtt::Message;
 use phf::phf_map;
@@ -10,14 +12,70 @@ use serde_derive::Deserialize;
 use serde_derive::Serialize;
 use std::time::{Duration, SystemTime};
 
-/// Maps topics to QoS
+/// Maps topics to QoS. Per node topics use `+` in place of the node id.
 pub static TOPICS: phf::Map<&'static str, i32> = phf_map! {
     "/connect" => 2,
     "/disconnect" => 2,
     "/zero" => 1,
+    "/zero/result" => 2,
     "/sensors/detection" => 2,
+    "/config/+" => 1,
+    "/config/+/applied" => 1,
+    "/nodes/+/status" => 0,
+    "/clock/+/ping" => 0,
+    "/clock/+/pong" => 0,
 };
 
+/// Gets the QoS of a topic, including per node topics like `/config/3`.
+pub fn topic_qos(topic: &str) -> Option<i32> {
+    TOPICS.get(topic).copied().or_else(|| {
+        TOPICS
+            .entries()
+            .find(|(pattern, _)| topic_matches(pattern, topic))
+            .map(|(_, qos)| *qos)
+    })
+}
+
+/// Checks if topic matches pattern, where `+` matches any one level.
+fn topic_matches(pattern: &str, topic: &str) -> bool {
+    let mut pattern = pattern.split('/');
+    let mut topic = topic.split('/');
+
+    loop {
+        match (pattern.next(), topic.next()) {
+            (None, None) => return true,
+            (Some("+"), Some(_)) => {}
+            (Some(p), Some(t)) if p == t => {}
+            _ => return false,
+        }
+    }
+}
+
+/// Topic that configures a node.
+pub fn config_topic(node_id: u16) -> String {
+    format!("/config/{}", node_id)
+}
+
+/// Topic a node publishes its applied configuration to.
+pub fn config_applied_topic(node_id: u16) -> String {
+    format!("/config/{}/applied", node_id)
+}
+
+/// Topic a node publishes its health to.
+pub fn status_topic(node_id: u16) -> String {
+    format!("/nodes/{}/status", node_id)
+}
+
+/// Topic a node is pinged on, to measure its clock.
+pub fn clock_ping_topic(node_id: u16) -> String {
+    format!("/clock/{}/ping", node_id)
+}
+
+/// Topic a node answers pings on.
+pub fn clock_pong_topic(node_id: u16) -> String {
+    format!("/clock/{}/pong", node_id)
+}
+
 /// All possible timebay messages.
 ///
 /// This type is designed to be used with `try_from` and `try_into` to covert raw Mqtt messages into their
@@ -52,6 +110,20 @@ pub enum MqttMessage {
     Detection(DetectionMessage),
     /// Zeros all sensors
     Zero,
+    /// A node finished zeroing
+    ZeroResult(ZeroResultMessage),
+    /// Changes the settings of a node
+    Config(NodeConfigMessage),
+    /// Settings a node is using, after a change or on startup
+    #[from(ignore)]
+    #[try_into(ignore)]
+    ConfigApplied(NodeConfigMessage),
+    /// Health of a node, sent periodically
+    Status(NodeStatusMessage),
+    /// Asks a node for its clock
+    ClockPing(ClockPingMessage),
+    /// A node's answer to a ping
+    ClockPong(ClockPongMessage),
     /// A message on an unknown topic
     #[try_into(ignore)]
     Unknown(String),
@@ -68,9 +140,29 @@ impl TryFrom<Message> for MqttMessage {
                 Ok(postcard::from_bytes::<DisconnectionMessage>(value.payload())?.into())
             }
             "/zero" => Ok(Zero),
+            "/zero/result" => {
+                Ok(postcard::from_bytes::<ZeroResultMessage>(value.payload())?.into())
+            }
             "/sensors/detection" => {
                 Ok(postcard::from_bytes::<DetectionMessage>(value.payload())?.into())
             }
+            topic if topic_matches("/config/+/applied", topic) => Ok(ConfigApplied(
+                postcard::from_bytes::<NodeConfigMessage>(value.payload())?,
+            )),
+            topic if topic_matches("/nodes/+/status", topic) => {
+                Ok(postcard::from_bytes::<NodeStatusMessage>(value.payload())?.into())
+            }
+            topic if topic_matches("/clock/+/ping", topic) => {
+                Ok(postcard::from_bytes::<ClockPingMessage>(value.payload())?.into())
+            }
+            topic if topic_matches("/clock/+/pong", topic) => {
+                Ok(postcard::from_bytes::<ClockPongMessage>(value.payload())?.into())
+            }
+            topic if topic_matches("/config/+", topic) => {
+                Ok(Config(postcard::from_bytes::<NodeConfigMessage>(
+                    value.payload(),
+                )?))
+            }
 
             _ => Ok(Unknown(value.topic().into())),
         }
@@ -99,6 +191,37 @@ impl TryFrom<MqttMessage> for Message {
                 TOPICS["/sensors/detection"],
             )),
             MqttMessage::Zero => Ok(Message::new("/zero", [], TOPICS["/zero"])),
+            MqttMessage::ZeroResult(res) => Ok(Message::new(
+                "/zero/result",
+                postcard::to_allocvec(&res)?,
+                TOPICS["/zero/result"],
+            )),
+            Config(conf) => Ok(Message::new(
+                config_topic(conf.node_id),
+                postcard::to_allocvec(&conf)?,
+                TOPICS["/config/+"],
+            )),
+            // Retained, so clients see the settings of every node when they connect
+            ConfigApplied(conf) => Ok(Message::new_retained(
+                config_applied_topic(conf.node_id),
+                postcard::to_allocvec(&conf)?,
+                TOPICS["/config/+/applied"],
+            )),
+            Status(status) => Ok(Message::new(
+                status_topic(status.node_id),
+                postcard::to_allocvec(&status)?,
+                TOPICS["/nodes/+/status"],
+            )),
+            ClockPing(ping) => Ok(Message::new(
+                clock_ping_topic(ping.node_id),
+                postcard::to_allocvec(&ping)?,
+                TOPICS["/clock/+/ping"],
+            )),
+            ClockPong(pong) => Ok(Message::new(
+                clock_pong_topic(pong.node_id),
+                postcard::to_allocvec(&pong)?,
+                TOPICS["/clock/+/pong"],
+            )),
             Unknown(_) => Err(NonConvertable),
         }
     }
@@ -116,33 +239,243 @@ pub struct DisconnectionMessage {
     pub node_id: u16,
 }
 
-/// Message published on vehicle detection.
-#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Copy, Clone, Constructor)]
+/// Message published on vehicle detection, once the vehicle has passed.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Copy, Clone)]
 pub struct DetectionMessage {
     pub node_id: u16,
     /// Detection distance in mm.
     pub dist: u32,
-    /// Detection time in unix seconds.
+    /// Detection time in unix seconds. This is when the vehicle crossed the threshold, interpolated
+    /// between readings.
     pub stamp_s: u64,
     /// Nanoseconds fraction of the unix stamp.
     pub stamp_ns: u32,
+    /// Time the vehicle left view in unix seconds.
+    pub exit_stamp_s: u64,
+    /// Nanoseconds fraction of the unix exit stamp.
+    pub exit_stamp_ns: u32,
+    /// Closest the vehicle came in mm.
+    pub min_dist: u32,
+    /// Readings the vehicle was seen in.
+    pub samples: u32,
+    /// Count of detections the node made before this one since it started. Detections queued
+    /// while the node was disconnected are published late, in order.
+    pub seq: u32,
+    /// Random id picked each time the node starts. Sequence numbers start from 0 each boot, so
+    /// they only compare within one.
+    pub boot_id: u32,
+}
+
+/// Outcome of a node zeroing.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Copy, Clone)]
+pub enum ZeroStatus {
+    /// Zeroed off of valid readings
+    #[default]
+    Zeroed,
+    /// Too few readings were valid, as nothing is within range. The old zero was kept.
+    NoWall,
+    /// Too few readings were valid, as the signal was too weak or strong. The old zero was kept.
+    LowSignal,
+    /// Readings varied too much, so the old zero was kept
+    Unstable,
+    /// The sensor gave no readings, so the old zero was kept
+    SensorFailed,
+}
+
+/// Message published by a node after zeroing.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Copy, Clone, Constructor)]
+pub struct ZeroResultMessage {
+    pub node_id: u16,
+    /// Zero in use after zeroing, mm.
+    pub zero: u32,
+    /// Variance of the readings averaged, mm^2.
+    pub variance: u32,
+    pub status: ZeroStatus,
+}
+
+/// Settings of a node that can be changed at runtime.
+///
+/// When changing settings, None leaves a setting as is. When applied, None means the node does
+/// not support the setting.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Copy, Clone, Constructor)]
+pub struct NodeConfigMessage {
+    pub node_id: u16,
+    /// Delta off of zero to count as a trigger, mm
+    pub threshold: Option<u32>,
+    /// Hold off time of the node's debounce mode, ms. In clear mode, how long nothing must be in
+    /// view before re-arming; in gap mode, the least time between vehicles arriving. Train mode
+    /// has none, so it is left as is when set and is 0 when applied.
+    pub debounce_ms: Option<u64>,
+    /// Sensor frame rate, Hz
+    pub frame_rate: Option<u16>,
+}
+
+/// Health of a node, published periodically. Counters are totals since the node started.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone)]
+pub struct NodeStatusMessage {
+    pub node_id: u16,
+    /// Time since the node started, s.
+    pub uptime_s: u64,
+    /// Sensor chip temperature, °C. None if the sensor doesn't report it.
+    pub temp_c: Option<i16>,
+    /// Mean signal strength since the last status. None if the sensor doesn't report it.
+    pub mean_amp: Option<u16>,
+    /// Zero in use, mm.
+    pub zero: u32,
+    /// Delta off of zero to count as a trigger, mm.
+    pub threshold: u32,
+    /// Readings taken per second since the last status.
+    pub readings_per_s: u32,
+    /// Frames from the sensor that failed their checksum.
+    pub checksum_failures: u64,
+    /// Failed reads from the sensor.
+    pub read_errors: u64,
+    /// Times the node reconnected to the broker.
+    pub reconnects: u32,
+    /// Software version of the node.
+    pub version: String,
+    /// How well the node's clock is synchronised. None if it couldn't be read.
+    pub clock: Option<ClockSync>,
+}
+
+/// How well a node's clock is synchronised, as reported by chrony.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Copy, Clone)]
+pub struct ClockSync {
+    /// Offset of the clock from the time source, µs. Positive when the clock is ahead.
+    pub offset_us: i64,
+    /// Bound on how far the clock is from true time, µs.
+    pub error_us: u64,
+    /// NTP stratum of the clock, 0 if it has no source.
+    pub stratum: u8,
+    /// If the clock is synchronised to a source.
+    pub synced: bool,
+}
+
+/// Asks a node for its clock, so its offset can be measured like NTP does. Stamps are unix ns.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Copy, Clone)]
+pub struct ClockPingMessage {
+    pub node_id: u16,
+    /// Picked by the measuring client, so it can tell its pings from those of other clients.
+    pub client_id: u32,
+    /// Time the ping was sent, by the measuring client's clock.
+    pub sent_ns: u64,
+}
+
+/// A node's answer to a ping. Stamps are unix ns.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Copy, Clone)]
+pub struct ClockPongMessage {
+    pub node_id: u16,
+    /// Client id of the ping.
+    pub client_id: u32,
+    /// Time the ping was sent, by the measuring client's clock.
+    pub ping_sent_ns: u64,
+    /// Time the ping was received, by the node's clock.
+    pub received_ns: u64,
+    /// Time the pong was sent, by the node's clock.
+    pub sent_ns: u64,
+}
+
+impl ClockPingMessage {
+    /// Creates a ping sent now.
+    pub fn new(node_id: u16, client_id: u32) -> Self {
+        Self {
+            node_id,
+            client_id,
+            sent_ns: unix_ns(SystemTime::now()),
+        }
+    }
+
+    /// Answers the ping, which was received at received. Send the pong as soon as possible after
+    /// creating it.
+    pub fn pong(&self, received: SystemTime) -> ClockPongMessage {
+        ClockPongMessage {
+            node_id: self.node_id,
+            client_id: self.client_id,
+            ping_sent_ns: self.sent_ns,
+            received_ns: unix_ns(received),
+            sent_ns: unix_ns(SystemTime::now()),
+        }
+    }
+}
+
+/// Converts a time into unix ns.
+pub fn unix_ns(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64
 }
 
 impl DetectionMessage {
+    /// Creates a detection seen in a single reading.
+    pub fn new(node_id: u16, dist: u32, stamp_s: u64, stamp_ns: u32) -> Self {
+        Self {
+            node_id,
+            dist,
+            stamp_s,
+            stamp_ns,
+            exit_stamp_s: stamp_s,
+            exit_stamp_ns: stamp_ns,
+            min_dist: dist,
+            samples: 1,
+            seq: 0,
+            boot_id: 0,
+        }
+    }
+
+    /// Sets how the vehicle passed, after it left view.
+    pub fn with_exit(mut self, exit_stamp: SystemTime, min_dist: u32, samples: u32) -> Self {
+        let exit = exit_stamp
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        self.exit_stamp_s = exit.as_secs();
+        self.exit_stamp_ns = exit.subsec_nanos();
+        self.min_dist = min_dist;
+        self.samples = samples;
+        self
+    }
+
     /// Returns the contained unix timestamp as a real time value.
     pub fn get_stamp(&self) -> SystemTime {
         SystemTime::UNIX_EPOCH
             + Duration::from_secs(self.stamp_s)
             + Duration::from_nanos(self.stamp_ns as u64)
     }
+
+    /// Returns the time the vehicle left view as a real time value.
+    pub fn get_exit_stamp(&self) -> SystemTime {
+        SystemTime::UNIX_EPOCH
+            + Duration::from_secs(self.exit_stamp_s)
+            + Duration::from_nanos(self.exit_stamp_ns as u64)
+    }
+
+    /// Sets the sequence number of the detection, within the node's boot.
+    pub fn with_seq(mut self, boot_id: u32, seq: u32) -> Self {
+        self.boot_id = boot_id;
+        self.seq = seq;
+        self
+    }
+
+    /// Time the vehicle was in view. Divide the vehicle's length by this for its speed.
+    pub fn dwell(&self) -> Duration {
+        self.get_exit_stamp()
+            .duration_since(self.get_stamp())
+            .unwrap_or_default()
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::messages::MqttMessage::{Connection, Detection, Disconnection, Zero};
-    use crate::messages::{ConnectionMessage, DetectionMessage, DisconnectionMessage, MqttMessage};
+    use crate::messages::MqttMessage::{
+        ClockPing, ClockPong, Config, ConfigApplied, Connection, Detection, Disconnection, Status,
+        Zero, ZeroResult,
+    };
+    use crate::messages::{
+        topic_qos, ClockPingMessage, ClockSync, ConnectionMessage, DetectionMessage,
+        DisconnectionMessage, MqttMessage, NodeConfigMessage, NodeStatusMessage, ZeroResultMessage,
+        ZeroStatus,
+    };
     use paho_mqtt::Message;
-    use std::time::{SystemTime, UNIX_EPOCH};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
     #[test]
     fn mqtt_message_parses() {
@@ -171,6 +504,13 @@ mod test {
         let msg: Message = Zero.try_into().unwrap();
         assert!(MqttMessage::try_from(msg).unwrap().is_zero());
 
+        let result = ZeroResultMessage::new(3, 1500, 4, ZeroStatus::NoWall);
+        let msg: Message = ZeroResult(result).try_into().unwrap();
+        assert_eq!(
+            MqttMessage::try_from(msg).unwrap().unwrap_zero_result(),
+            result
+        );
+
         let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
         let detect_msg = Detection(DetectionMessage::new(
             2,
@@ -184,5 +524,78 @@ mod test {
             MqttMessage::try_from(msg).unwrap().unwrap_detection(),
             detect_msg.unwrap_detection()
         );
+
+        let detect = DetectionMessage::new(2, 523, time.as_secs(), time.subsec_nanos()).with_exit(
+            UNIX_EPOCH + time + Duration::from_millis(250),
+            480,
+            25,
+        );
+        let msg: Message = Detection(detect).try_into().unwrap();
+        let parsed = MqttMessage::try_from(msg).unwrap().unwrap_detection();
+        assert_eq!(parsed, detect);
+        assert_eq!(parsed.dwell(), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn config_messages_parse() {
+        let conf = NodeConfigMessage::new(3, Some(300), None, Some(250));
+
+        let msg: Message = Config(conf).try_into().unwrap();
+        assert_eq!(msg.topic(), "/config/3");
+        assert_eq!(MqttMessage::try_from(msg).unwrap(), Config(conf));
+
+        let msg: Message = ConfigApplied(conf).try_into().unwrap();
+        assert_eq!(msg.topic(), "/config/3/applied");
+        assert!(msg.retained());
+        assert_eq!(MqttMessage::try_from(msg).unwrap(), ConfigApplied(conf));
+
+        assert_eq!(topic_qos("/config/12"), Some(1));
+        assert_eq!(topic_qos("/config/+/applied"), Some(1));
+        assert_eq!(topic_qos("/config/12/other"), None);
+        assert_eq!(topic_qos("/zero"), Some(1));
+    }
+
+    #[test]
+    fn status_message_parses() {
+        let status = NodeStatusMessage {
+            node_id: 4,
+            uptime_s: 3600,
+            temp_c: Some(41),
+            mean_amp: None,
+            zero: 1500,
+            threshold: 200,
+            readings_per_s: 100,
+            checksum_failures: 2,
+            read_errors: 0,
+            reconnects: 1,
+            version: "0.1.0".to_string(),
+            clock: Some(ClockSync {
+                offset_us: -120,
+                error_us: 2500,
+                stratum: 2,
+                synced: true,
+            }),
+        };
+
+        let msg: Message = Status(status.clone()).try_into().unwrap();
+        assert_eq!(msg.topic(), "/nodes/4/status");
+        assert_eq!(MqttMessage::try_from(msg).unwrap(), Status(status));
+    }
+
+    #[test]
+    fn clock_messages_parse() {
+        let ping = ClockPingMessage::new(5, 77);
+        let msg: Message = ClockPing(ping).try_into().unwrap();
+        assert_eq!(msg.topic(), "/clock/5/ping");
+        assert_eq!(MqttMessage::try_from(msg).unwrap(), ClockPing(ping));
+
+        let pong = ping.pong(SystemTime::now());
+        assert_eq!(pong.ping_sent_ns, ping.sent_ns);
+        assert!(pong.sent_ns >= pong.received_ns);
+
+        let msg: Message = ClockPong(pong).try_into().unwrap();
+        assert_eq!(msg.topic(), "/clock/5/pong");
+        assert_eq!(MqttMessage::try_from(msg).unwrap(), ClockPong(pong));
+        assert_eq!(topic_qos("/clock/5/pong"), Some(0));
     }
 }
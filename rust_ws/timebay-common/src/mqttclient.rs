@@ -2,7 +2,7 @@
 
 use crate::error::MqttClientError as Error;
 use crate::error::MqttClientError::ExplicitDisconnect;
-use crate::messages::{MqttMessage, TOPICS};
+use crate::messages::{topic_qos, MqttMessage};
 use paho_mqtt::{AsyncClient, AsyncReceiver, ConnectOptions, CreateOptionsBuilder, Message};
 
 /// Mqtt client abstraction.
@@ -33,7 +33,10 @@ impl MqttClient {
         let stream = client.get_stream(10);
 
         // Sub to topics
-        let qoss: Vec<_> = subs.iter().map(|t| TOPICS[t]).collect();
+        let qoss: Vec<_> = subs
+            .iter()
+            .map(|t| topic_qos(t).unwrap_or_else(|| panic!("Unknown topic {}", t)))
+            .collect();
         client.subscribe_many(subs, &qoss);
 
         Ok(Self {
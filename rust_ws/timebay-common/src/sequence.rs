@@ -0,0 +1,78 @@
+//! Dropping repeated detections, and noticing missed ones, by their sequence numbers.
+
+use crate::messages::DetectionMessage;
+use std::collections::HashMap;
+
+/// How a detection's sequence number compares to those seen before from its node
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SeqCheck {
+    /// The next detection, or the first from this boot of the node
+    New,
+    /// Already seen, like when republished after a reconnect
+    Duplicate,
+    /// New, but this many detections before it never arrived
+    Gap(u32),
+}
+
+/// Tracks the latest detection seen from each node.
+#[derive(Debug, Default, Clone)]
+pub struct SeqTracker {
+    /// Boot id and highest sequence number seen, per node
+    latest: HashMap<u16, (u32, u32)>,
+}
+
+impl SeqTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks a detection against those seen before, recording it if it is new.
+    pub fn check(&mut self, msg: &DetectionMessage) -> SeqCheck {
+        let latest = self.latest.get(&msg.node_id).copied();
+
+        let check = match latest {
+            // A restarted node numbers from 0 again, so only compare within a boot
+            Some((boot_id, seq)) if boot_id == msg.boot_id => {
+                if msg.seq <= seq {
+                    return SeqCheck::Duplicate;
+                }
+
+                match msg.seq - seq - 1 {
+                    0 => SeqCheck::New,
+                    missed => SeqCheck::Gap(missed),
+                }
+            }
+            _ => SeqCheck::New,
+        };
+
+        self.latest.insert(msg.node_id, (msg.boot_id, msg.seq));
+        check
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::messages::DetectionMessage;
+    use crate::sequence::{SeqCheck, SeqTracker};
+
+    #[test]
+    fn repeats_and_gaps_found() {
+        let mut tracker = SeqTracker::new();
+        let detect = |node, boot, seq| DetectionMessage::new(node, 700, 1, 0).with_seq(boot, seq);
+
+        assert_eq!(tracker.check(&detect(1, 7, 4)), SeqCheck::New);
+        assert_eq!(tracker.check(&detect(1, 7, 5)), SeqCheck::New);
+        assert_eq!(tracker.check(&detect(1, 7, 5)), SeqCheck::Duplicate);
+        assert_eq!(tracker.check(&detect(1, 7, 3)), SeqCheck::Duplicate);
+
+        // Other nodes are tracked separately
+        assert_eq!(tracker.check(&detect(2, 7, 0)), SeqCheck::New);
+
+        assert_eq!(tracker.check(&detect(1, 7, 9)), SeqCheck::Gap(3));
+        assert_eq!(tracker.check(&detect(1, 7, 10)), SeqCheck::New);
+
+        // Node restarted
+        assert_eq!(tracker.check(&detect(1, 8, 0)), SeqCheck::New);
+        assert_eq!(tracker.check(&detect(1, 8, 0)), SeqCheck::Duplicate);
+    }
+}
@@ -6,10 +6,17 @@ use crate::splits::Splits;
 use cursive::traits::Nameable;
 use cursive::views::{Dialog, LinearLayout, TextView};
 use derive_more::IsVariant;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::future::Future;
 use std::sync::Arc;
-use timebay_common::messages::{ConnectionMessage, DetectionMessage, DisconnectionMessage};
+use std::time::{Duration, SystemTime};
+use timebay_common::clock::ClockModel;
+use timebay_common::messages::{
+    ClockPingMessage, ClockPongMessage, ClockSync, ConnectionMessage, DetectionMessage,
+    DisconnectionMessage, MqttMessage, NodeConfigMessage, NodeStatusMessage, ZeroResultMessage,
+    ZeroStatus,
+};
+use timebay_common::sequence::{SeqCheck, SeqTracker};
 
 /// App connection state
 #[derive(Debug, IsVariant, Clone)]
@@ -35,6 +42,16 @@ pub enum AppMessage {
     SendZero,
     /// Zero op completed
     ZeroAck,
+    /// A node reported how zeroing went
+    ZeroResult(ZeroResultMessage),
+    /// Sensor node reported the settings it is using
+    NodeConfig(NodeConfigMessage),
+    /// Node settings were edited
+    SendConfig(NodeConfigMessage),
+    /// Sensor node reported its health
+    NodeStatus(NodeStatusMessage),
+    /// Sensor node answered a clock ping, received at the given time
+    ClockPong(ClockPongMessage, SystemTime),
     /// Does nothing
     Nop,
 }
@@ -45,6 +62,22 @@ pub struct App {
     state: AppState,
     /// Connected sensor node ids
     connected_nodes: BTreeSet<u16>,
+    /// Last zero result of each node
+    zeros: BTreeMap<u16, ZeroResultMessage>,
+    /// Settings each node last reported using
+    node_configs: BTreeMap<u16, NodeConfigMessage>,
+    /// Health each node last reported
+    statuses: BTreeMap<u16, NodeStatusMessage>,
+    /// Sequence numbers of detections seen, to drop repeats
+    seqs: SeqTracker,
+    /// Detections each node made that never arrived
+    missed: BTreeMap<u16, u32>,
+    /// Largest clock error a node can have before its sectors are flagged
+    clock_budget: Duration,
+    /// Offsets of node clocks from ours, measured by pinging them
+    clocks: ClockModel,
+    /// If detection stamps are moved onto our clock using the measured offsets
+    correct_clocks: bool,
     /// Current lap we are timing
     lap: Splits,
     /// Last lap
@@ -54,24 +87,106 @@ pub struct App {
 }
 
 impl App {
-    /// Creates a new application
-    pub fn new() -> Self {
+    /// Creates a new application, flagging sectors timed by nodes with clocks off by more than
+    /// clock_budget. If correct_clocks, detections are corrected by the offsets measured from
+    /// pinging nodes.
+    pub fn new(clock_budget: Duration, correct_clocks: bool) -> Self {
         Self {
             state: AppState::Connecting,
             connected_nodes: BTreeSet::new(),
+            zeros: BTreeMap::new(),
+            node_configs: BTreeMap::new(),
+            statuses: BTreeMap::new(),
+            seqs: SeqTracker::new(),
+            missed: BTreeMap::new(),
+            clock_budget,
+            // Other clients pinging only need a different id, not a random one
+            clocks: ClockModel::new(std::process::id()),
+            correct_clocks,
             lap: Splits::new(BTreeSet::new()),
             last_lap: None,
             last_last_lap: None,
         }
     }
 
+    /// Connected sensor node ids
+    pub fn connected_nodes(&self) -> &BTreeSet<u16> {
+        &self.connected_nodes
+    }
+
+    /// Settings a node last reported using, if it has
+    pub fn node_config(&self, node_id: u16) -> Option<&NodeConfigMessage> {
+        self.node_configs.get(&node_id)
+    }
+
+    /// Checks if a node last reported its clock out of budget, or if correcting clocks, that its
+    /// measured offset is too uncertain. Nodes that can't read their clock aren't flagged, as that
+    /// is usually a development setup.
+    fn clock_bad(&self, node_id: u16) -> bool {
+        let measured_bad = self.correct_clocks
+            && self
+                .clocks
+                .estimate(node_id)
+                .is_some_and(|estimate| estimate.error() > self.clock_budget);
+
+        measured_bad
+            || self
+                .statuses
+                .get(&node_id)
+                .and_then(|status| status.clock)
+                .is_some_and(|clock| !clock_in_budget(&clock, self.clock_budget))
+    }
+
+    /// Describes the offset of a node's clock measured by pinging it.
+    fn measured_label(&self, node_id: u16) -> String {
+        match self.clocks.estimate(node_id) {
+            Some(estimate) => {
+                let flag = if self.correct_clocks && estimate.error() > self.clock_budget {
+                    " OVER BUDGET"
+                } else {
+                    ""
+                };
+                format!(
+                    "measured {:+.1}ms ±{:.1}ms{}",
+                    estimate.offset_ns as f64 / 1e6,
+                    estimate.error().as_secs_f64() * 1000.0,
+                    flag
+                )
+            }
+            None => "not measured".to_string(),
+        }
+    }
+
+    /// Describes a node's clock sync, flagging it if out of budget.
+    fn clock_label(&self, status: &NodeStatusMessage) -> String {
+        match status.clock {
+            Some(clock) if !clock.synced => "CLOCK NOT SYNCED".to_string(),
+            Some(clock) => {
+                let error = Duration::from_micros(clock.error_us);
+                let flag = if clock_in_budget(&clock, self.clock_budget) {
+                    ""
+                } else {
+                    " OVER BUDGET"
+                };
+                format!(
+                    "clock {:+.1}ms ±{:.1}ms stratum {}{}",
+                    clock.offset_us as f64 / 1000.0,
+                    error.as_secs_f64() * 1000.0,
+                    clock.stratum,
+                    flag
+                )
+            }
+            None => "CLOCK UNKNOWN".to_string(),
+        }
+    }
+
     /// Generates the main body view based off current app state
     pub fn view(&self) -> impl cursive::view::View {
         if self.state.is_connecting() {
-            LinearLayout::horizontal()
+            LinearLayout::vertical()
                 .child(Dialog::around(TextView::new("Connecting to gateway...")))
         } else {
-            LinearLayout::horizontal()
+            let laps = LinearLayout::horizontal()
                 .child(
                     if let Some(ref last) = self.last_lap {
                         Dialog::around(last.view(&self.last_last_lap))
@@ -87,15 +202,42 @@ impl App {
                         .with_name("current_lap"),
                 )
                 .child(
-                    Dialog::around(
-                        self.connected_nodes
-                            .iter()
-                            .fold(LinearLayout::horizontal(), |agg, n| {
-                                agg.child(TextView::new(format!("| {} |", n)))
-                            }),
-                    )
+                    Dialog::around(self.connected_nodes.iter().fold(
+                        LinearLayout::horizontal(),
+                        |agg, n| {
+                            agg.child(TextView::new(match self.zeros.get(n) {
+                                Some(res) => format!("| {} {} |", n, zero_label(res)),
+                                None => format!("| {} |", n),
+                            }))
+                        },
+                    ))
                     .title("Connected sensors"),
-                )
+                );
+
+            let health = self
+                .connected_nodes
+                .iter()
+                .map(|n| {
+                    let line = match self.statuses.get(n) {
+                        Some(status) => format!(
+                            "{} | {} | {}",
+                            status_line(status),
+                            self.clock_label(status),
+                            self.measured_label(*n)
+                        ),
+                        None => format!("{}: waiting for status | {}", n, self.measured_label(*n)),
+                    };
+                    match self.missed.get(n) {
+                        Some(missed) => format!("{} | {} MISSED DETECTIONS", line, missed),
+                        None => line,
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            LinearLayout::vertical()
+                .child(laps)
+                .child(Dialog::around(TextView::new(health)).title("Node health"))
         }
     }
 
@@ -123,6 +265,21 @@ impl App {
 
                 // Add node to splits if we haven't started yet
                 self.lap.connect_node(id.node_id);
+
+                // Heartbeats are frequent enough to keep the node's clock measured
+                if let Connected { ref cli } = self.state {
+                    let cli_cl = cli.clone();
+                    let client_id = self.clocks.client_id();
+                    return Some(Box::new(async move {
+                        // Stamped here, so time spent queued for the runtime isn't counted
+                        let ping = ClockPingMessage::new(id.node_id, client_id);
+                        if cli_cl.publish(MqttMessage::ClockPing(ping)).await.is_err() {
+                            AppMessage::StateChange(AppState::Connecting)
+                        } else {
+                            AppMessage::Nop
+                        }
+                    }));
+                }
             }
             AppMessage::DisconnectNode(id) => {
                 log::info!("Sensor node: {} disconnected", id.node_id);
@@ -132,15 +289,61 @@ impl App {
 
                 // Disconnect node from splits if we haven't begun yet
                 self.lap.disconnect_node(id.node_id);
+                self.clocks.forget(id.node_id);
             }
             AppMessage::Detection(detc) => {
                 log::trace!(
-                    "Node {} triggered with dist: {} and stamp: {}.{}",
+                    "Node {} triggered with dist: {} and stamp: {}.{}, in view for {:?} and {}mm away at closest",
                     detc.node_id,
                     detc.dist,
                     detc.stamp_s,
-                    detc.stamp_ns
+                    detc.stamp_ns,
+                    detc.dwell(),
+                    detc.min_dist
                 );
+
+                // Repeats would look like the vehicle passing the node again, ending the lap
+                match self.seqs.check(&detc) {
+                    SeqCheck::New => {}
+                    SeqCheck::Duplicate => {
+                        log::warn!(
+                            "Dropping repeated detection {} from node {}",
+                            detc.seq,
+                            detc.node_id
+                        );
+                        return None;
+                    }
+                    SeqCheck::Gap(missed) => {
+                        log::warn!(
+                            "Node {} missed {} detections before {}",
+                            detc.node_id,
+                            missed,
+                            detc.seq
+                        );
+                        *self.missed.entry(detc.node_id).or_default() += missed;
+                    }
+                }
+
+                let detc = if self.correct_clocks {
+                    self.clocks.correct(detc)
+                } else {
+                    detc
+                };
+
+                // Sectors are only as good as the clocks that timed them
+                if self.clock_bad(detc.node_id) {
+                    log::warn!(
+                        "Node {} detected with its clock out of sync, flagging its sectors",
+                        detc.node_id
+                    );
+                    self.lap.flag_clock(detc.node_id);
+                }
+
+                // Late detections may belong to the lap before
+                if self.last_lap.as_mut().is_some_and(|lap| lap.fill_in(&detc)) {
+                    return None;
+                }
+
                 if self.lap.handle_node_trigger(detc).is_completed() {
                     // Swap current lap to last lap when done
                     self.last_last_lap = self.last_lap.clone();
@@ -173,9 +376,94 @@ impl App {
             AppMessage::ZeroAck => {
                 log::trace!("Zero returned success");
             }
+            AppMessage::ZeroResult(res) => {
+                if res.status == ZeroStatus::Zeroed {
+                    log::info!(
+                        "Node {} zeroed to {}mm with variance {}mm^2",
+                        res.node_id,
+                        res.zero,
+                        res.variance
+                    );
+                } else {
+                    log::warn!("Node {} failed to zero with {:?}", res.node_id, res.status);
+                }
+                self.zeros.insert(res.node_id, res);
+            }
+            AppMessage::NodeConfig(conf) => {
+                log::info!("Node {} is using {:?}", conf.node_id, conf);
+                self.node_configs.insert(conf.node_id, conf);
+            }
+            AppMessage::SendConfig(conf) => {
+                if let Connected { ref cli } = self.state {
+                    log::trace!("sending config to node {}", conf.node_id);
+
+                    let cli_cl = cli.clone();
+                    return Some(Box::new(async move {
+                        if cli_cl.publish(MqttMessage::Config(conf)).await.is_err() {
+                            AppMessage::StateChange(AppState::Connecting)
+                        } else {
+                            AppMessage::Nop
+                        }
+                    }));
+                }
+            }
+            AppMessage::NodeStatus(status) => {
+                log::debug!("Node {} reported {:?}", status.node_id, status);
+                self.statuses.insert(status.node_id, status);
+            }
+            AppMessage::ClockPong(pong, received) => {
+                if let Some(sample) = self.clocks.record(&pong, received) {
+                    log::trace!("Node {} measured with {:?}", pong.node_id, sample);
+                }
+            }
             AppMessage::Nop => {}
         };
 
         None
     }
 }
+
+/// Checks if a clock is synced, with error within budget.
+fn clock_in_budget(clock: &ClockSync, budget: Duration) -> bool {
+    clock.synced && Duration::from_micros(clock.error_us) <= budget
+}
+
+/// One line summary of a node's health.
+fn status_line(status: &NodeStatusMessage) -> String {
+    let uptime = Duration::from_secs(status.uptime_s);
+    let temp = status
+        .temp_c
+        .map(|temp| format!("{}°C", temp))
+        .unwrap_or_else(|| "--°C".to_string());
+    let amp = status
+        .mean_amp
+        .map(|amp| amp.to_string())
+        .unwrap_or_else(|| "--".to_string());
+
+    format!(
+        "{}: v{} up {}h{:02}m | {} amp {} | {}Hz | zero {}mm threshold {}mm | {} checksum failures, {} read errors, {} reconnects",
+        status.node_id,
+        status.version,
+        uptime.as_secs() / 3600,
+        uptime.as_secs() / 60 % 60,
+        temp,
+        amp,
+        status.readings_per_s,
+        status.zero,
+        status.threshold,
+        status.checksum_failures,
+        status.read_errors,
+        status.reconnects
+    )
+}
+
+/// Short description of a zero result, flagging failures.
+fn zero_label(res: &ZeroResultMessage) -> String {
+    match res.status {
+        ZeroStatus::Zeroed => format!("{}mm", res.zero),
+        ZeroStatus::NoWall => "NO WALL".to_string(),
+        ZeroStatus::LowSignal => "LOW SIGNAL".to_string(),
+        ZeroStatus::Unstable => "UNSTABLE".to_string(),
+        ZeroStatus::SensorFailed => "ZERO FAILED".to_string(),
+    }
+}
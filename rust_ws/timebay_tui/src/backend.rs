@@ -63,7 +63,11 @@ pub fn backend_thread(
         // Update doesn't have access to gui, so we cheat a bit and do other updates here (update could have this, but it makes the code less portable)
         if msg.is_zero_ack() {
             gui_tx
-                .send(Box::new(|s| s.add_layer(Dialog::info("Zeroed sensors"))))
+                .send(Box::new(|s| {
+                    s.add_layer(Dialog::info(
+                        "Zeroing sensors, see connected sensors for results",
+                    ))
+                }))
                 .unwrap();
             continue;
         }
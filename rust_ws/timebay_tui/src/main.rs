@@ -3,6 +3,7 @@ mod backend;
 mod error;
 mod mqtt;
 mod mqttsub;
+mod node_config;
 mod splits;
 
 use crate::app::{App, AppMessage};
@@ -16,6 +17,11 @@ use flexi_logger::{DeferredNow, FileSpec, Logger};
 use log::Record;
 use std::ops::Deref;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Largest clock error a node can have before its sectors are flagged, if not set by
+/// CLOCK_BUDGET_MS
+const DEFAULT_CLOCK_BUDGET: Duration = Duration::from_millis(5);
 
 fn main() {
     // Broker host can be passed via CLI
@@ -40,7 +46,14 @@ fn main() {
     // Communications between gui and background thread
     let (backend_tx, backend_rx) = crossfire::mpsc::bounded_tx_blocking_rx_future(10);
     let cb_sink = siv.cb_sink().clone();
-    let app = App::new();
+    let clock_budget = std::env::var("CLOCK_BUDGET_MS")
+        .ok()
+        .and_then(|ms| ms.parse().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_CLOCK_BUDGET);
+    // Measured offsets are always shown, but only used when asked to, as chrony is usually better
+    let correct_clocks = std::env::var("CORRECT_CLOCKS").is_ok_and(|val| val == "1");
+    let app = App::new(clock_budget, correct_clocks);
 
     siv.add_layer(app.view());
 
@@ -61,19 +74,23 @@ fn main() {
         cursive_flexi_logger_view::toggle_flexi_logger_debug_console,
     );
     siv.add_global_callback('z', zero_sensors);
+    siv.add_global_callback('c', node_config::configure_node);
 
     // Setup top menubar, since the app only gives the body
     siv.menubar()
         .add_subtree(
             "Actions",
-            Tree::new().with(|tree| tree.add_leaf("Zero Sensors", zero_sensors)),
+            Tree::new().with(|tree| {
+                tree.add_leaf("Zero Sensors", zero_sensors);
+                tree.add_leaf("Configure Sensor", node_config::configure_node);
+            }),
         )
         .add_subtree(
             "Help",
             Tree::new().with(|tree| {
                 tree.add_leaf("Controls", |s| {
                     s.add_layer(Dialog::info(
-                        "Press Q to quit, ~ for debug logs, z to zero sensors",
+                        "Press Q to quit, ~ for debug logs, z to zero sensors, c to configure a sensor",
                     ))
                 })
             }),
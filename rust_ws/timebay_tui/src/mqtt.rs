@@ -31,7 +31,15 @@ impl DerefMut for MqttClient {
 impl MqttClient {
     pub async fn connect(server_id: &str) -> Result<Self, Error> {
         // Topics to sub to
-        let subs = ["/connect", "/disconnect", "/sensors/detection"];
+        let subs = [
+            "/connect",
+            "/disconnect",
+            "/sensors/detection",
+            "/zero/result",
+            "/config/+/applied",
+            "/nodes/+/status",
+            "/clock/+/pong",
+        ];
 
         // Connect to broker
         let cli = timebay_common::mqttclient::MqttClient::connect(server_id, "client", &subs, None)
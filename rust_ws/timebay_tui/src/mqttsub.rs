@@ -4,6 +4,7 @@ use crate::app::{AppMessage, AppState};
 use crate::mqtt::MqttClient;
 use futures::Stream;
 use std::sync::Arc;
+use std::time::SystemTime;
 use timebay_common::error::MqttClientError;
 use timebay_common::messages::MqttMessage;
 
@@ -61,6 +62,19 @@ pub fn mqtt_subscription(broker_host: String) -> impl Stream<Item = AppMessage>
                         MqttMessage::Detection(msg) => {
                             Some((AppMessage::Detection(msg), State::Connected(client)))
                         }
+                        MqttMessage::ZeroResult(msg) => {
+                            Some((AppMessage::ZeroResult(msg), State::Connected(client)))
+                        }
+                        MqttMessage::ConfigApplied(msg) => {
+                            Some((AppMessage::NodeConfig(msg), State::Connected(client)))
+                        }
+                        MqttMessage::Status(msg) => {
+                            Some((AppMessage::NodeStatus(msg), State::Connected(client)))
+                        }
+                        MqttMessage::ClockPong(msg) => Some((
+                            AppMessage::ClockPong(msg, SystemTime::now()),
+                            State::Connected(client),
+                        )),
                         _ => Some((AppMessage::Nop, State::Connected(client))),
                     },
                     Err(err) => match err {
@@ -0,0 +1,114 @@
+//! Dialogs for editing the settings of sensor nodes
+
+use crate::app::AppMessage;
+use crate::backend::SharedState;
+use cursive::traits::*;
+use cursive::views::{Dialog, EditView, ListView, SelectView};
+use cursive::Cursive;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use timebay_common::messages::NodeConfigMessage;
+
+/// Opens a dialog to pick a connected node to configure.
+pub fn configure_node(s: &mut Cursive) {
+    let nodes: Vec<u16> = {
+        let app = s.user_data::<Arc<Mutex<SharedState>>>().unwrap();
+        let app = app.lock().unwrap();
+        app.app.connected_nodes().iter().copied().collect()
+    };
+
+    if nodes.is_empty() {
+        s.add_layer(Dialog::info("No sensors connected"));
+        return;
+    }
+
+    let select = SelectView::new()
+        .with_all(nodes.into_iter().map(|id| (format!("Node {}", id), id)))
+        .on_submit(|s, id: &u16| {
+            s.pop_layer();
+            edit_node(s, *id);
+        });
+
+    s.add_layer(
+        Dialog::around(select)
+            .title("Configure sensor")
+            .dismiss_button("Cancel"),
+    );
+}
+
+/// Opens a dialog to edit a node's settings, filled with those it last reported.
+fn edit_node(s: &mut Cursive, node_id: u16) {
+    let conf = {
+        let app = s.user_data::<Arc<Mutex<SharedState>>>().unwrap();
+        let app = app.lock().unwrap();
+        app.app.node_config(node_id).copied()
+    }
+    .unwrap_or_default();
+
+    let field = |value: Option<String>, name: &str| {
+        EditView::new()
+            .content(value.unwrap_or_default())
+            .with_name(name)
+            .fixed_width(8)
+    };
+
+    let list = ListView::new()
+        .child(
+            "Threshold (mm)",
+            field(conf.threshold.map(|v| v.to_string()), "threshold"),
+        )
+        .child(
+            "Debounce (ms)",
+            field(conf.debounce_ms.map(|v| v.to_string()), "debounce_ms"),
+        )
+        .child(
+            "Frame rate (Hz)",
+            field(conf.frame_rate.map(|v| v.to_string()), "frame_rate"),
+        );
+
+    s.add_layer(
+        Dialog::around(list)
+            .title(format!("Node {} settings", node_id))
+            .button("Apply", move |s| send_config(s, node_id))
+            .dismiss_button("Cancel"),
+    );
+}
+
+/// Sends the settings in the edit dialog to the node. Empty fields are left unchanged.
+fn send_config(s: &mut Cursive, node_id: u16) {
+    let Some(conf) = read_config(s, node_id) else {
+        s.add_layer(Dialog::info("Settings must be whole numbers"));
+        return;
+    };
+
+    s.pop_layer();
+
+    // Because backend handles this message, if we aren't connected nothing will happen
+    let tx = s.user_data::<Arc<Mutex<SharedState>>>().unwrap();
+    let tx = tx.lock().unwrap();
+    while tx.backend_tx.send(AppMessage::SendConfig(conf)).is_err() {}
+}
+
+/// Reads the settings in the edit dialog, or None if any are invalid.
+fn read_config(s: &mut Cursive, node_id: u16) -> Option<NodeConfigMessage> {
+    Some(NodeConfigMessage::new(
+        node_id,
+        parse_field(s, "threshold")?,
+        parse_field(s, "debounce_ms")?,
+        parse_field(s, "frame_rate")?,
+    ))
+}
+
+/// Parses an edit field, giving Some(None) if it is empty and None if it is invalid.
+fn parse_field<T: FromStr>(s: &mut Cursive, name: &str) -> Option<Option<T>> {
+    let text = s
+        .call_on_name(name, |view: &mut EditView| view.get_content())
+        .unwrap();
+    let text = text.trim();
+
+    if text.is_empty() {
+        Some(None)
+    } else {
+        text.parse().ok().map(Some)
+    }
+}
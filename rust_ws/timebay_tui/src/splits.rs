@@ -120,6 +120,11 @@ impl Splits {
             return self.state.clone();
         }
 
+        // Detections queued while a node was disconnected arrive late, after the lap moved past it
+        if self.fill_in(&msg) {
+            return self.state.clone();
+        }
+
         // If next expected node triggered, sector is complete
         if self.get_current_sector().nodes.1 == msg.node_id {
             log::trace!("Completed sector {}", self.current_sector);
@@ -165,6 +170,9 @@ impl Splits {
             self.sectors[self.current_sector..=sector_containing]
                 .iter_mut()
                 .for_each(|s| s.state = SectorState::Invalidated);
+
+            // Keep when we got here, in case the skipped detections arrive late
+            self.sectors[sector_containing].seen = Some(msg.get_stamp());
         }
         // If a passed node triggered again, end run. This is done to ensure the run can be reset if last node dies. (accept edge case if it was rejected above, since node ordering is reversed there)
         else if self.get_current_sector().nodes.1 > msg.node_id
@@ -198,6 +206,67 @@ impl Splits {
         self.state.clone()
     }
 
+    /// Fills in a sector that was invalidated because its end node's detection arrived late, like
+    /// after the node reconnected. Returns if the detection was used.
+    ///
+    /// This also works on completed laps, for detections arriving after the lap ended.
+    pub fn fill_in(&mut self, msg: &DetectionMessage) -> bool {
+        let (start, end) = match self.state {
+            SplitState::NotStarted => return false,
+            SplitState::Running(start) => (start, None),
+            SplitState::Completed(start, end) => (start, Some(end)),
+        };
+        let Some(idx) = self.get_sector_containing(msg.node_id) else {
+            return false;
+        };
+
+        // Only sectors that were skipped over can be missing a detection
+        let sector = &self.sectors[idx];
+        let passed = self.state.is_completed() || idx < self.current_sector;
+        if !passed || !sector.state.is_invalidated() || sector.seen.is_some() {
+            return false;
+        }
+
+        // The detection must fit between those either side of it
+        let stamp = msg.get_stamp();
+        let before = match idx {
+            0 => Some(start),
+            _ => self.sectors[idx - 1].end_stamp(),
+        };
+        let after = self
+            .sectors
+            .get(idx + 1)
+            .and_then(Sector::end_stamp)
+            .or(end);
+        if before.is_some_and(|b| stamp <= b) || after.is_some_and(|a| stamp >= a) {
+            return false;
+        }
+
+        log::info!("Filling in sector {} with a late detection", idx);
+        self.sectors[idx].seen = Some(stamp);
+
+        // Complete sectors once both their ends are known, following on to those after
+        for i in idx..self.sectors.len() {
+            let start_known = i == 0 || self.sectors[i - 1].state.is_complete();
+            match (start_known, self.sectors[i].seen) {
+                (true, Some(seen)) if self.sectors[i].state.is_invalidated() => {
+                    self.sectors[i].state = SectorState::Complete(seen)
+                }
+                _ => break,
+            }
+        }
+
+        true
+    }
+
+    /// Flags the sectors either side of a node, as its clock is out of sync.
+    pub fn flag_clock(&mut self, node: u16) {
+        self.sectors
+            .iter_mut()
+            .filter(|s| s.nodes.0 == node || s.nodes.1 == node)
+            .for_each(|s| s.clock_suspect = true);
+    }
+
     fn get_current_sector(&mut self) -> &mut Sector {
         &mut self.sectors[self.current_sector]
     }
@@ -249,7 +318,13 @@ impl Splits {
         for (i, sector) in self.sectors.iter().enumerate() {
             // Add sector name
             let name = format!("Sector {}-{}", sector.nodes.0, sector.nodes.1);
-            sectors.push(Panel::new(TextView::new(name)));
+            if sector.clock_suspect {
+                sectors.push(Panel::new(
+                    TextView::new(name + " CLOCK?").style(Color::Rgb(255, 165, 0)),
+                ));
+            } else {
+                sectors.push(Panel::new(TextView::new(name)));
+            }
 
             // Add sector time if done
             match sector.state {
@@ -466,6 +541,10 @@ struct Sector {
     state: SectorState,
     /// Nodes this sector is between
     nodes: (u16, u16),
+    /// When the ending node triggered, if it did but the sector is invalid
+    seen: Option<SystemTime>,
+    /// If either node's clock was out of sync, so the time may be off
+    clock_suspect: bool,
 }
 
 impl Sector {
@@ -473,6 +552,16 @@ impl Sector {
         Self {
             state: Incomplete,
             nodes: (starting_node, ending_node),
+            seen: None,
+            clock_suspect: false,
+        }
+    }
+
+    /// When the ending node triggered, if known
+    fn end_stamp(&self) -> Option<SystemTime> {
+        match self.state {
+            SectorState::Complete(time) => Some(time),
+            _ => self.seen,
         }
     }
 }
@@ -575,6 +664,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn late_detections_fill_in() {
+        let mut splits = Splits::new(BTreeSet::from_iter(1u16..=4));
+        let detect = |node, secs| DetectionMessage::new(node, 10, secs, 0);
+
+        splits.handle_node_trigger(detect(1, 10));
+        // Nodes 2 and 3 were disconnected, so 4 looks like a skip
+        splits.handle_node_trigger(detect(4, 40));
+        assert_eq!(splits.get_sector_times(), vec![None, None, None, None]);
+
+        // Their queued detections then arrive, out of the lap's order
+        assert!(splits.handle_node_trigger(detect(3, 30)).is_running());
+        assert_eq!(splits.get_sector_times(), vec![None, None, None, None]);
+        assert!(splits.handle_node_trigger(detect(2, 20)).is_running());
+
+        let secs = |s| Some(Duration::from_secs(s));
+        assert_eq!(
+            splits.get_sector_times(),
+            vec![secs(10), secs(10), secs(10), None]
+        );
+
+        // Nothing is left to fill in, so a repeat still ends the run
+        assert!(!splits.fill_in(&detect(2, 25)));
+        assert!(splits.handle_node_trigger(detect(1, 50)).is_completed());
+        assert_eq!(splits.get_sector_times()[3], secs(10));
+    }
+
     #[test]
     fn time_formatting() {
         let time = Duration::from_secs(62);
@@ -19,4 +19,4 @@ fi
 [ -n "$(docker images -q timebay:sensor)" ] || docker build -t timebay:sensor -f sensor-node.dockerfile .
 
 # Launch sensor node, restarting if it crashed
-docker run --privileged --rm --network host -e NODE_ID=1 -e BROKER_HOST=gateway --add-host=gateway:192.168.0.1 --volume /dev:/dev timebay:sensor
\ No newline at end of file
+docker run --privileged --rm --network host -e NODE_ID=1 -e BROKER_HOST=gateway --add-host=gateway:192.168.0.1 --volume /dev:/dev -e REMOTE_CONFIG=/var/lib/timebay/sensor_node_remote.toml -e QUEUE_FILE=/var/lib/timebay/sensor_node_queue.toml --volume /var/lib/timebay:/var/lib/timebay timebay:sensor
\ No newline at end of file
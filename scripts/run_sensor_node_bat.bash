@@ -26,4 +26,4 @@ if [ -z "$1" ]
 fi
 
 # Launch sensor node, restarting if it crashed
-docker run --privileged --rm --network host -e NODE_ID="$1" -e BROKER_HOST=gateway --add-host=gateway:192.168.0.1 --cap-add SYS_TIME --volume /dev:/dev --entrypoint "/sensor_node_bringup.bash" timebay:sensor
+docker run --privileged --rm --network host -e NODE_ID="$1" -e BROKER_HOST=gateway --add-host=gateway:192.168.0.1 --cap-add SYS_TIME --volume /dev:/dev -e REMOTE_CONFIG=/var/lib/timebay/sensor_node_remote.toml -e QUEUE_FILE=/var/lib/timebay/sensor_node_queue.toml --volume /var/lib/timebay:/var/lib/timebay --entrypoint "/sensor_node_bringup.bash" timebay:sensor